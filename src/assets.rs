@@ -0,0 +1,112 @@
+//! Loading the asset files that define the bot's flavour text.
+//!
+//! Assets are checked before they're used, both at startup and when they're reloaded, so that a
+//! typo in a yaml file can't take down a running bot.
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use tracing::warn;
+
+use crate::commands::action::ActionCommandData;
+use crate::commands::say_hi::SayHiData;
+use crate::config::Config;
+use crate::triggers::{TriggerRule, Triggers};
+use crate::validate::{self, Diagnostic, Severity};
+
+/// The contents of every asset file
+#[derive(Debug)]
+pub struct Assets {
+    pub actions: Vec<ActionCommandData>,
+    pub say_hi: Vec<SayHiData>,
+    pub triggers: Triggers,
+}
+
+#[derive(Debug)]
+pub enum AssetError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, serde_yaml::Error),
+    /// The file parsed, but the data in it doesn't make sense
+    Invalid(PathBuf, Vec<String>),
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetError::Io(path, e) => write!(f, "couldn't read {}: {e}", path.display()),
+            AssetError::Parse(path, e) => write!(f, "couldn't parse {}: {e}", path.display()),
+            AssetError::Invalid(path, problems) => {
+                write!(f, "{} is invalid:", path.display())?;
+                for problem in problems {
+                    write!(f, "\n  - {problem}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+impl Assets {
+    /// Reads and checks every asset file. Warnings are logged, and errors stop the assets from
+    /// loading.
+    pub fn load(config: &Config) -> Result<Self, AssetError> {
+        let paths = &config.assets;
+
+        let (text, actions): (_, Vec<ActionCommandData>) = read_yaml(&paths.actions)?;
+        check(
+            &paths.actions,
+            validate::check_actions(&paths.actions, &text, &actions, config),
+        )?;
+
+        let (text, say_hi): (_, Vec<SayHiData>) = read_yaml(&paths.say_hi)?;
+        check(
+            &paths.say_hi,
+            validate::check_say_hi(&paths.say_hi, &text, &say_hi),
+        )?;
+
+        let (text, rules): (_, Vec<TriggerRule>) = read_yaml(&paths.triggers)?;
+        check(
+            &paths.triggers,
+            validate::check_triggers(&paths.triggers, &text, &rules),
+        )?;
+        let triggers = Triggers::new(rules)
+            .map_err(|e| AssetError::Invalid(paths.triggers.clone(), vec![e.to_string()]))?;
+
+        Ok(Self {
+            actions,
+            say_hi,
+            triggers,
+        })
+    }
+}
+
+/// Reads a yaml file, returning its text along with the parsed data
+pub fn read_yaml<T: DeserializeOwned>(path: &Path) -> Result<(String, T), AssetError> {
+    let text = fs::read_to_string(path).map_err(|e| AssetError::Io(path.to_owned(), e))?;
+    let data = serde_yaml::from_str(&text).map_err(|e| AssetError::Parse(path.to_owned(), e))?;
+    Ok((text, data))
+}
+
+/// Logs any warnings, and turns any errors into an [AssetError]
+fn check(path: &Path, diagnostics: Vec<Diagnostic>) -> Result<(), AssetError> {
+    let mut problems = Vec::new();
+
+    for diagnostic in diagnostics {
+        match diagnostic.severity {
+            Severity::Warning => warn!("{diagnostic}"),
+            Severity::Error => problems.push(match diagnostic.line {
+                Some(line) => format!("line {line}: {}", diagnostic.message),
+                None => diagnostic.message,
+            }),
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(AssetError::Invalid(path.to_owned(), problems))
+    }
+}
@@ -1,20 +1,41 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
 use rand::seq::SliceRandom;
 use serde::Deserialize;
 use serenity::{
-    all::{CommandInteraction, CommandOptionType, ResolvedValue, User},
-    builder::{
-        CreateCommand, CreateCommandOption, CreateEmbed, CreateInteractionResponse,
-        CreateInteractionResponseMessage,
+    all::{
+        ActionRowComponent, ButtonStyle, CommandInteraction, CreateActionRow, CreateButton,
+        CreateInteractionResponse, CreateInteractionResponseMessage, EditInteractionResponse,
+        GuildId, Mentionable, Message, MessageId, User, UserId,
     },
+    builder::{CreateCommand, CreateEmbed, CreateEmbedFooter},
     model::Colour,
-    prelude::Context,
     utils::MessageBuilder,
 };
-use tracing::{error, info, instrument};
+use tokio::time::Instant;
+use tracing::error;
 
+use crate::components::{create_component_fn, ComponentEvent, ComponentFn, CustomId};
+use crate::discord::Discord;
 use crate::event_handler::Handler;
+use crate::gifs::GifRequest;
+use crate::storage::{ActionCounts, ActionUse};
+
+use super::cooldown::{Bucket, Cooldown};
+use super::error::{report_error, CommandError, CommandResult};
+use super::help::{Category, HelpDetails};
+use super::options::{command_options, CommandOptions};
+use super::responder::{Reply, Responder};
+use super::{create_command_fn, CommandDetails};
+
+/// The namespace of the buttons for answering an action
+pub const NAMESPACE: &str = "action";
 
-use super::{create_command_fn, help::HelpDetails, CommandDetails};
+/// How long a message is remembered as answered. Its buttons are gone long before this, so it's
+/// only to catch presses that were already on their way
+const ANSWER_MEMORY: Duration = Duration::from_secs(15 * 60);
 
 const EMBED_COLOURS: &[Colour] = &[
     Colour::FABLED_PINK,
@@ -28,117 +49,380 @@ const EMBED_COLOURS: &[Colour] = &[
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
 pub struct ActionCommandData {
-    kind: String,
-    description: String,
+    pub kind: String,
+    #[serde(default)]
+    pub category: Category,
+    pub description: String,
     #[serde(rename = "targeted messages")]
-    targeted_messages: Option<Vec<String>>,
+    pub targeted_messages: Option<Vec<String>>,
     #[serde(rename = "lonely messages")]
-    lonely_messages: Vec<String>,
+    pub lonely_messages: Vec<String>,
     #[serde(rename = "nano messages")]
-    nano_messages: Option<Vec<String>>,
+    pub nano_messages: Option<Vec<String>>,
+    #[serde(default)]
+    pub gifs: Vec<String>,
+    pub providers: Option<Vec<String>>,
+    /// A footer that keeps a running tally of how many times the action has been used
+    pub tally: Option<String>,
+    /// An action the target gets a button for doing back, like a hug for a hug
+    pub reciprocal: Option<String>,
+    /// An action the target gets a button for answering with, like a slap for a poke
+    pub counter: Option<String>,
 }
 
 impl ActionCommandData {
-    fn targetable(&self) -> bool {
+    pub fn targetable(&self) -> bool {
         self.targeted_messages.is_some() && self.nano_messages.is_some()
     }
 }
 
-#[derive(Clone, Debug, Deserialize)]
-struct ImageResponse {
-    url: String,
+/// Action commands fetch a gif every time, so they can't be spammed. A few in a row is fine though
+fn spam_cooldown() -> Cooldown {
+    Cooldown::new(Bucket::User, Duration::from_secs(15)).uses(3)
 }
 
-#[instrument(skip(client))]
-async fn get_image(client: &reqwest::Client, kind: &str) -> reqwest::Result<String> {
-    info!("sending request to otakugifs");
-    let response = client.get(format!("https://api.otakugifs.xyz/gif?reaction={kind}"))
-        .send()
-        .await?
-        .json::<ImageResponse>()
-        .await?;
+/// Fills in the `<count>` and `<total>` placeholders
+fn fill_counts(template: &str, counts: ActionCounts) -> String {
+    template
+        .replace("<count>", &counts.count.to_string())
+        .replace("<total>", &counts.total.to_string())
+}
+
+/// Picks a random message template. If the action couldn't be counted, templates that show the
+/// counts are avoided (unless there's nothing else)
+fn choose_template(templates: &[String], counted: bool) -> &str {
+    let mut rng = rand::thread_rng();
+    let uncounted = templates
+        .iter()
+        .filter(|template| !template.contains("<count>") && !template.contains("<total>"))
+        .collect::<Vec<_>>();
 
-    info!("recieved reponse - okay");
-    Ok(response.url)
+    if counted || uncounted.is_empty() {
+        templates.choose(&mut rng).unwrap()
+    } else {
+        uncounted.choose(&mut rng).unwrap()
+    }
 }
 
-pub async fn run(
-    kind: &str,
-    ctx: Context,
-    cmd: &CommandInteraction,
-    handler: Handler,
-) {
-    let options = cmd.data.options();
-    let data = handler.actions.iter().find(|data| data.kind == kind).unwrap();
-    let user_mention = MessageBuilder::new().mention(&cmd.user).build();
-
-    let message = if options.is_empty()
-        || matches!(options[0].value, ResolvedValue::User(u, _) if u == &cmd.user)
-    {
-        let mut rng = rand::thread_rng();
-        let template = data.lonely_messages.choose(&mut rng).unwrap();
-        template.replace("<user>", &user_mention)
-    } else if data.targetable() {
-        if options.len() != 1 {
-            error!("somehow, more options were passed to the command than should be possible. fix that.");
-            return;
-        }
+command_options! {
+    struct ActionOptions {
+        /// the user to target
+        target: Option<User>,
+    }
+}
 
-        let nano: User = ctx.http.get_current_user().await.unwrap().into();
+/// The buttons for the target to answer an action with, if it has any. Only `target` can press
+/// them, and they do the action back to `user`
+fn answer_buttons(data: &ActionCommandData, user: UserId, target: UserId) -> Vec<CreateButton> {
+    let button = |answer: &str, label: String, style| {
+        let id = CustomId::new(NAMESPACE, answer, format!("{target}:{user}"));
+        CreateButton::new(id).label(label).style(style)
+    };
 
-        let ResolvedValue::User(target, _) = options[0].value else {
-            error!("option passed to slash command is of incorrect type.");
-            return;
-        };
+    let reciprocal = data.reciprocal.as_deref().map(|answer| {
+        let label = format!("{} back", capitalise(answer));
+        button(answer, label, ButtonStyle::Primary)
+    });
 
-        let mut rng = rand::thread_rng();
-        let target_mention = MessageBuilder::new().mention(target).build();
-
-        let template = if target == &nano {
-            data.nano_messages
-                .as_ref()
-                .unwrap()
-                .choose(&mut rng)
-                .unwrap()
-        } else {
-            data.targeted_messages
-                .as_ref()
-                .unwrap()
-                .choose(&mut rng)
-                .unwrap()
-        };
+    // Buttons with the same custom id aren't allowed, and validation should have caught this
+    let counter = data
+        .counter
+        .as_deref()
+        .filter(|counter| data.reciprocal.as_deref() != Some(*counter))
+        .map(|answer| button(answer, capitalise(answer), ButtonStyle::Danger));
 
-        template
-            .replace("<user>", &user_mention)
-            .replace("<target>", &target_mention)
-    } else {
-        error!("command is not targetable, yet target was passed anyway");
-        return;
+    reciprocal.into_iter().chain(counter).collect()
+}
+
+fn capitalise(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Does an action, and makes the message for it. The target has already been checked not to be
+/// the user
+async fn act(
+    data: &ActionCommandData,
+    discord: &Discord,
+    handler: &Handler,
+    user: UserId,
+    target: Option<UserId>,
+    guild: Option<GuildId>,
+) -> Result<Reply, CommandError> {
+    let kind = data.kind.as_str();
+    let user_mention = MessageBuilder::new().mention(&user).build();
+
+    let (templates, nano_targeted) = match target {
+        None => (&data.lonely_messages, false),
+
+        Some(target) => {
+            let (Some(targeted_messages), Some(nano_messages)) =
+                (&data.targeted_messages, &data.nano_messages)
+            else {
+                return Err(CommandError::BadOption(format!(
+                    "I can't {kind} someone else, only myself!"
+                )));
+            };
+
+            let nano = discord.current_user().await?;
+            if target == nano.id {
+                (nano_messages, true)
+            } else {
+                (targeted_messages, false)
+            }
+        }
     };
 
-    let image = match get_image(&handler.http_client, kind).await {
-        Ok(res) => res,
+    let action_use = ActionUse {
+        kind: kind.to_owned(),
+        user,
+        target,
+        guild,
+        at: SystemTime::now(),
+    };
+
+    // Losing count isn't worth failing the whole command over
+    let counts = match handler.storage.record_action(action_use).await {
+        Ok(counts) => Some(counts),
         Err(e) => {
-            error!("couldnt get image: {e}");
-            return;
+            error!("couldn't count the action: {e}");
+            None
         }
     };
 
+    let mut message = choose_template(templates, counts.is_some()).replace("<user>", &user_mention);
+
+    if let Some(target) = target {
+        let target_mention = MessageBuilder::new().mention(&target).build();
+        message = message.replace("<target>", &target_mention);
+    }
+
+    if let Some(counts) = counts {
+        message = fill_counts(&message, counts);
+    }
+
     let colour = {
         let mut rng = rand::thread_rng();
         *EMBED_COLOURS.choose(&mut rng).unwrap()
     };
 
-    let embed = CreateEmbed::new().image(image).colour(colour);
-    let response_message = CreateInteractionResponseMessage::new()
-        .content(message)
-        .embed(embed);
+    let mut reply = Reply::new().content(message);
+
+    let request = GifRequest {
+        kind,
+        gifs: &data.gifs,
+    };
+
+    let image = handler.gifs.find(request, data.providers.as_deref()).await;
+    if image.is_none() {
+        error!("couldnt get an image from any source, responding without one");
+    }
+
+    let tally = data.tally.as_deref().zip(counts);
+    if image.is_some() || tally.is_some() {
+        let mut embed = CreateEmbed::new().colour(colour);
+
+        if let Some(image) = image {
+            embed = embed.image(image);
+        }
+
+        if let Some((tally, counts)) = tally {
+            embed = embed.footer(CreateEmbedFooter::new(fill_counts(tally, counts)));
+        }
+
+        reply = reply.embed(embed);
+    }
+
+    // Nano doesn't press buttons
+    if let Some(target) = target.filter(|_| !nano_targeted) {
+        for button in answer_buttons(data, user, target) {
+            reply = reply.button(button);
+        }
+    }
+
+    Ok(reply)
+}
+
+/// Finds the data for an action
+async fn action_data(handler: &Handler, kind: &str) -> Option<ActionCommandData> {
+    handler
+        .actions
+        .read()
+        .await
+        .iter()
+        .find(|data| data.kind == kind)
+        .cloned()
+}
+
+pub async fn run(
+    kind: &str,
+    discord: Discord,
+    cmd: &CommandInteraction,
+    handler: Handler,
+    responder: Responder,
+) -> CommandResult {
+    let data = action_data(&handler, kind)
+        .await
+        .ok_or_else(|| CommandError::UnknownCommand(kind.to_owned()))?;
+
+    let options = ActionOptions::parse(&cmd.data.options())?;
+    let target = options
+        .target
+        .map(|target| target.id)
+        .filter(|target| *target != cmd.user.id);
+
+    let reply = act(&data, &discord, &handler, cmd.user.id, target, cmd.guild_id).await?;
+    responder.respond(reply).await?;
+    Ok(())
+}
+
+/// Does an action back, when its target presses one of the buttons from [answer_buttons]
+/// The action messages that have been answered, so that pressing a button twice (or two buttons
+/// at once) only answers once
+#[derive(Debug, Default)]
+pub struct Answers {
+    started: Mutex<HashMap<MessageId, Instant>>,
+}
+
+impl Answers {
+    /// Starts answering a message, unless it already has been
+    fn start(&self, message: MessageId) -> bool {
+        let now = Instant::now();
+        let mut started = self.started.lock().unwrap();
+        started.retain(|_, at| now.duration_since(*at) < ANSWER_MEMORY);
+
+        if started.contains_key(&message) {
+            return false;
+        }
+
+        started.insert(message, now);
+        true
+    }
+
+    /// Lets a message be answered again, after an answer didn't work
+    fn cancel(&self, message: MessageId) {
+        self.started.lock().unwrap().remove(&message);
+    }
+}
+
+/// The buttons on a message, to put back if taking them off was premature
+fn buttons(message: &Message) -> Vec<CreateActionRow> {
+    message
+        .components
+        .iter()
+        .map(|row| {
+            let buttons = row
+                .components
+                .iter()
+                .filter_map(|component| match component {
+                    ActionRowComponent::Button(button) => Some(button.clone().into()),
+                    _ => None,
+                })
+                .collect();
+            CreateActionRow::Buttons(buttons)
+        })
+        .collect()
+}
+
+async fn answer(
+    discord: Discord,
+    handler: Handler,
+    event: ComponentEvent,
+    id: CustomId,
+) -> CommandResult {
+    let ComponentEvent::Component(interaction) = &event else {
+        return Err(CommandError::Expired);
+    };
+
+    let parse = |id: &str| {
+        id.parse::<u64>()
+            .ok()
+            .filter(|id| *id != 0)
+            .map(UserId::new)
+    };
+    let (answerer, user) = id
+        .payload
+        .split_once(':')
+        .and_then(|(answerer, user)| Some((parse(answerer)?, parse(user)?)))
+        .ok_or(CommandError::Expired)?;
+
+    if interaction.user.id != answerer {
+        let reply = Reply::new()
+            .content(format!(
+                "Sorry! Only {} can answer that.",
+                answerer.mention()
+            ))
+            .ephemeral(true);
+        let response = CreateInteractionResponse::Message(reply.into_message());
+        event.respond(&discord, response).await?;
+        return Ok(());
+    }
+
+    // The answer might have been removed, or turned off in this server, since the buttons were made
+    let kind = id.action.as_str();
+    let data = action_data(&handler, kind)
+        .await
+        .ok_or(CommandError::Expired)?;
+    handler
+        .check_command_allowed(kind, interaction.guild_id, interaction.channel_id)
+        .await?;
+
+    if !handler.answers.start(interaction.message.id) {
+        let reply = Reply::new()
+            .content("That's already been answered!")
+            .ephemeral(true);
+        let response = CreateInteractionResponse::Message(reply.into_message());
+        event.respond(&discord, response).await?;
+        return Ok(());
+    }
+
+    // The buttons go straight away, so discord doesn't give up waiting while the answer is made.
+    // They're put back if it doesn't work out
+    let responder = Responder::for_component(Arc::clone(&discord), &event);
+    let remove_buttons = CreateInteractionResponseMessage::new().components(Vec::new());
+    if let Err(e) = responder.update(remove_buttons).await {
+        handler.answers.cancel(interaction.message.id);
+        return Err(e.into());
+    }
+
+    let result = act(
+        &data,
+        &discord,
+        &handler,
+        answerer,
+        Some(user),
+        interaction.guild_id,
+    )
+    .await;
+
+    match result {
+        Ok(reply) => responder.followup(reply).await?,
 
-    let response = CreateInteractionResponse::Message(response_message);
+        Err(e) => {
+            handler.answers.cancel(interaction.message.id);
+
+            let restore = EditInteractionResponse::new().components(buttons(&interaction.message));
+            if let Err(e) = discord
+                .edit_original_interaction_response(event.token(), &restore)
+                .await
+            {
+                error!("couldn't put the answer buttons back: {e}");
+            }
 
-    if let Err(e) = cmd.create_response(&ctx.http, response).await {
-        error!("error sending response message: {e}");
+            // The interaction has been responded to, so this sends the error as a followup
+            report_error(&responder, e).await;
+        }
     }
+
+    Ok(())
+}
+
+/// Handles the buttons for answering actions
+pub fn components() -> ComponentFn {
+    create_component_fn(answer)
 }
 
 pub fn register(commands_data: &[ActionCommandData]) -> Vec<CommandDetails> {
@@ -149,19 +433,18 @@ pub fn register(commands_data: &[ActionCommandData]) -> Vec<CommandDetails> {
         let help = HelpDetails {
             name: data.kind.clone(),
             details: data.description.clone(),
+            category: data.category,
             sub_commands: Vec::new(),
         };
 
         if data.targetable() {
-            let target =
-                CreateCommandOption::new(CommandOptionType::User, "target", "the user to target");
-            registration = registration.add_option(target);
+            registration = registration.set_options(ActionOptions::options());
         }
 
-        // Hacky solution but not so bad. Will try to use less memory later
-        let kind: &'static str = data.kind.clone().leak();
-        let command = create_command_fn(move |ctx, handler, cmd| async move {
-            run(kind, ctx, &cmd, handler).await
+        let kind = data.kind.clone();
+        let command = create_command_fn(move |discord, handler, cmd, responder| {
+            let kind = kind.clone();
+            async move { run(&kind, discord, &cmd, handler, responder).await }
         });
 
         commands.push(CommandDetails {
@@ -169,6 +452,8 @@ pub fn register(commands_data: &[ActionCommandData]) -> Vec<CommandDetails> {
             registration,
             help,
             command,
+            autocomplete: None,
+            cooldowns: vec![spam_cooldown()],
         });
     }
 
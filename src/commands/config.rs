@@ -0,0 +1,407 @@
+//! `/config`, for server admins to change how Nano works in their server.
+//!
+//! The settings themselves live in a [GuildSettingsStore](crate::guild_settings::GuildSettingsStore),
+//! and are enforced by the dispatcher.
+use std::fmt;
+
+use serenity::all::{
+    AutocompleteChoice, ChannelId, CommandInteraction, CreateEmbed, GuildId, Mentionable,
+    PartialChannel, Permissions,
+};
+
+use crate::event_handler::Handler;
+use crate::guild_settings::can_be_restricted;
+
+use super::error::{CommandError, CommandResult};
+use super::help::{suggest_commands, Category};
+use super::options::{command_options, CommandOptions};
+use super::responder::{Reply, Responder};
+use super::subcommands::{leaf_options, CommandTree, SubCommand, SubCommandGroup};
+use super::{create_autocomplete_fn, create_command_fn, CommandDetails};
+
+command_options! {
+    struct ToggleOptions {
+        /// a command
+        command: Option<String> => set_autocomplete(true),
+        /// a whole category of commands
+        category: Option<String> =>
+            add_string_choice(Category::Affection.title(), Category::Affection.name())
+            .add_string_choice(Category::Reactions.title(), Category::Reactions.name())
+            .add_string_choice(Category::Fun.title(), Category::Fun.name())
+            .add_string_choice(Category::Utility.title(), Category::Utility.name()),
+    }
+}
+
+command_options! {
+    struct RestrictOptions {
+        /// the command to restrict
+        command: String => set_autocomplete(true),
+        /// a channel it can be used in
+        channel: PartialChannel,
+    }
+}
+
+command_options! {
+    struct UnrestrictOptions {
+        /// the command to let be used anywhere
+        command: String => set_autocomplete(true),
+        /// just stop allowing it in this channel
+        channel: Option<PartialChannel>,
+    }
+}
+
+/// What's being turned on or off
+enum Toggle {
+    Command(String, Category),
+    Category(Category),
+}
+
+impl fmt::Display for Toggle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Toggle::Command(name, _) => write!(f, "/{name}"),
+            Toggle::Category(category) => write!(f, "{} commands", category.title()),
+        }
+    }
+}
+
+/// The server the command was used in. Discord only shows /config to members who can manage
+/// the server (unless its admins say otherwise), so that's all the checking needed
+fn guild(cmd: &CommandInteraction) -> Result<GuildId, CommandError> {
+    cmd.guild_id
+        .ok_or_else(|| CommandError::BadOption("That only works in a server!".to_owned()))
+}
+
+/// Finds a command that can be restricted, returning its name and category
+async fn find_command(handler: &Handler, name: &str) -> Result<(String, Category), CommandError> {
+    let name = name.trim().trim_start_matches('/');
+    let data = handler.help_data.read().await;
+
+    match data.iter().find(|details| details.name == name) {
+        Some(details) if can_be_restricted(details.category) => {
+            Ok((details.name.clone(), details.category))
+        }
+        Some(_) => Err(CommandError::BadOption(format!(
+            "/{name} is too important to turn off!"
+        ))),
+        None => Err(CommandError::BadOption(format!(
+            "I don't have a command called \"{name}\"!"
+        ))),
+    }
+}
+
+async fn toggle(handler: &Handler, options: ToggleOptions) -> Result<Toggle, CommandError> {
+    match (options.command, options.category) {
+        (Some(name), None) => {
+            let (name, category) = find_command(handler, &name).await?;
+            Ok(Toggle::Command(name, category))
+        }
+
+        (None, Some(name)) => Category::from_name(&name)
+            .filter(|category| can_be_restricted(*category))
+            .map(Toggle::Category)
+            .ok_or_else(|| {
+                CommandError::BadOption(format!("I can't turn off \"{name}\" commands!"))
+            }),
+
+        _ => Err(CommandError::BadOption(
+            "Pick a command or a category (but not both)!".to_owned(),
+        )),
+    }
+}
+
+fn mention_channels<'a>(channels: impl IntoIterator<Item = &'a ChannelId>) -> String {
+    channels
+        .into_iter()
+        .map(|channel| channel.mention().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The most discord shows in an embed field
+const FIELD_LENGTH: usize = 1024;
+
+/// Puts `lines` in an embed field, leaving off the ones that don't fit
+fn field_value(lines: &[String]) -> String {
+    let mut value = String::new();
+
+    for (i, line) in lines.iter().enumerate() {
+        let left_over = lines.len() - i - 1;
+        let note = match left_over {
+            0 => 0,
+            n => format!("\n…and {n} more").chars().count(),
+        };
+        let separator = usize::from(!value.is_empty());
+        let length = value.chars().count() + separator + line.chars().count() + note;
+
+        if length <= FIELD_LENGTH {
+            if separator == 1 {
+                value.push('\n');
+            }
+            value.push_str(line);
+            continue;
+        }
+
+        if value.is_empty() {
+            // Even the first line doesn't fit (one command kept to lots of channels), so it's cut
+            // short instead
+            value = line.chars().take(FIELD_LENGTH - note - 1).collect();
+            value.push('…');
+            if left_over > 0 {
+                value.push_str(&format!("\n…and {left_over} more"));
+            }
+        } else {
+            value.push_str(&format!("\n…and {} more", left_over + 1));
+        }
+        break;
+    }
+
+    value
+}
+
+/// Turns a command or category on or off
+async fn set_enabled(
+    enabled: bool,
+    handler: Handler,
+    cmd: CommandInteraction,
+    responder: Responder,
+) -> CommandResult {
+    let guild = guild(&cmd)?;
+    let toggle = toggle(&handler, ToggleOptions::parse(&leaf_options(&cmd))?).await?;
+
+    let (changed, category_disabled) = handler
+        .guild_settings
+        .update(guild, |settings| {
+            let changed = match (&toggle, enabled) {
+                (Toggle::Command(name, _), true) => settings.disabled_commands.remove(name),
+                (Toggle::Command(name, _), false) => {
+                    settings.disabled_commands.insert(name.clone())
+                }
+                (Toggle::Category(category), true) => settings.disabled_categories.remove(category),
+                (Toggle::Category(category), false) => {
+                    settings.disabled_categories.insert(*category)
+                }
+            };
+
+            let category_disabled = match &toggle {
+                Toggle::Command(_, category) => settings.disabled_categories.contains(category),
+                Toggle::Category(_) => false,
+            };
+
+            (changed, category_disabled)
+        })
+        .await?;
+
+    let mut message = match (changed, enabled) {
+        (true, true) => format!("Done! {toggle} can be used again."),
+        (true, false) => format!("Done! {toggle} can't be used in this server any more."),
+        (false, true) => format!("{toggle} wasn't turned off!"),
+        (false, false) => format!("{toggle} is already turned off!"),
+    };
+
+    if let (Toggle::Command(_, category), true, true) = (&toggle, enabled, category_disabled) {
+        message += &format!(
+            "\nBut {} commands are still turned off, so it can't be used until they're turned back on.",
+            category.title()
+        );
+    }
+
+    responder
+        .respond(Reply::new().content(message).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
+/// Only lets a command be used in a channel (and any others it was already allowed in)
+async fn restrict(
+    handler: Handler,
+    cmd: CommandInteraction,
+    responder: Responder,
+) -> CommandResult {
+    let guild = guild(&cmd)?;
+    let options = RestrictOptions::parse(&leaf_options(&cmd))?;
+    let (name, _) = find_command(&handler, &options.command).await?;
+
+    let channels = handler
+        .guild_settings
+        .update(guild, |settings| {
+            let channels = settings.channels.entry(name.clone()).or_default();
+            channels.insert(options.channel.id);
+            channels.clone()
+        })
+        .await?;
+
+    let message = format!(
+        "Done! /{name} can only be used in {} now.",
+        mention_channels(&channels)
+    );
+    responder
+        .respond(Reply::new().content(message).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
+/// Lets a command be used in every channel again, or stops allowing it in just one
+async fn unrestrict(
+    handler: Handler,
+    cmd: CommandInteraction,
+    responder: Responder,
+) -> CommandResult {
+    let guild = guild(&cmd)?;
+    let options = UnrestrictOptions::parse(&leaf_options(&cmd))?;
+    let (name, _) = find_command(&handler, &options.command).await?;
+
+    let channels = handler
+        .guild_settings
+        .update(guild, |settings| {
+            match (&options.channel, settings.channels.get_mut(&name)) {
+                (Some(channel), Some(channels)) => {
+                    channels.remove(&channel.id);
+                    // Not being allowed in any channel would be the same as being turned off,
+                    // which has its own command
+                    if channels.is_empty() {
+                        settings.channels.remove(&name);
+                    }
+                }
+                (None, _) => {
+                    settings.channels.remove(&name);
+                }
+                (Some(_), None) => {}
+            }
+
+            settings.channels.get(&name).cloned()
+        })
+        .await?;
+
+    let message = match channels {
+        None => format!("Done! /{name} can be used in any channel."),
+        Some(channels) => format!(
+            "Done! /{name} can only be used in {} now.",
+            mention_channels(&channels)
+        ),
+    };
+    responder
+        .respond(Reply::new().content(message).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
+/// Shows everything that's been turned off or restricted
+async fn list(handler: Handler, cmd: CommandInteraction, responder: Responder) -> CommandResult {
+    let settings = handler.guild_settings.get(guild(&cmd)?).await?;
+    let mut embed = CreateEmbed::new().title("Command settings");
+
+    if settings.is_empty() {
+        embed = embed.description("Every command can be used anywhere here!");
+    }
+
+    let disabled = settings
+        .disabled_categories
+        .iter()
+        .map(|category| Toggle::Category(*category).to_string())
+        .chain(
+            settings
+                .disabled_commands
+                .iter()
+                .map(|name| format!("/{name}")),
+        )
+        .collect::<Vec<_>>();
+
+    if !disabled.is_empty() {
+        embed = embed.field("Turned off", field_value(&disabled), false);
+    }
+
+    if !settings.channels.is_empty() {
+        let channels = settings
+            .channels
+            .iter()
+            .map(|(name, channels)| format!("/{name}: {}", mention_channels(channels)))
+            .collect::<Vec<_>>();
+        embed = embed.field("Only allowed in", field_value(&channels), false);
+    }
+
+    responder
+        .respond(Reply::new().embed(embed).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
+/// Suggests commands that can be turned off or restricted
+async fn autocomplete(handler: Handler, cmd: &CommandInteraction) -> Vec<AutocompleteChoice> {
+    let Some(focused) = cmd.data.autocomplete() else {
+        return Vec::new();
+    };
+
+    if focused.name != "command" {
+        return Vec::new();
+    }
+
+    let data = handler.help_data.read().await;
+    suggest_commands(&data, focused.value, |details| {
+        can_be_restricted(details.category)
+    })
+}
+
+pub fn register() -> CommandDetails {
+    let autocomplete = create_autocomplete_fn(|_discord, handler, cmd| async move {
+        autocomplete(handler, &cmd).await
+    });
+
+    let disable = create_command_fn(|_discord, handler, cmd, responder| {
+        set_enabled(false, handler, cmd, responder)
+    });
+    let enable = create_command_fn(|_discord, handler, cmd, responder| {
+        set_enabled(true, handler, cmd, responder)
+    });
+    let restrict =
+        create_command_fn(|_discord, handler, cmd, responder| restrict(handler, cmd, responder));
+    let unrestrict =
+        create_command_fn(|_discord, handler, cmd, responder| unrestrict(handler, cmd, responder));
+    let list = create_command_fn(|_discord, handler, cmd, responder| list(handler, cmd, responder));
+
+    let commands = SubCommandGroup::new("commands", "Choose which commands can be used, and where")
+        .subcommand(
+            SubCommand::new(
+                "disable",
+                "Turn off a command, or a category of commands",
+                disable,
+            )
+            .options(ToggleOptions::options())
+            .autocomplete(autocomplete.clone()),
+        )
+        .subcommand(
+            SubCommand::new("enable", "Turn a command or category back on", enable)
+                .options(ToggleOptions::options())
+                .autocomplete(autocomplete.clone()),
+        )
+        .subcommand(
+            SubCommand::new(
+                "restrict",
+                "Only allow a command in a channel. Use it again to allow more channels",
+                restrict,
+            )
+            .options(RestrictOptions::options())
+            .autocomplete(autocomplete.clone()),
+        )
+        .subcommand(
+            SubCommand::new(
+                "unrestrict",
+                "Let a command be used in every channel again, or stop allowing it in one",
+                unrestrict,
+            )
+            .options(UnrestrictOptions::options())
+            .autocomplete(autocomplete),
+        )
+        .subcommand(SubCommand::new(
+            "list",
+            "See which commands are turned off or restricted",
+            list,
+        ));
+
+    CommandTree::new("config", "Change how Nano works in this server")
+        .category(Category::Admin)
+        .permissions(Permissions::MANAGE_GUILD)
+        .guild_only()
+        .group(commands)
+        .build()
+}
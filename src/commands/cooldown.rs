@@ -0,0 +1,141 @@
+//! Stopping commands from being spammed.
+//!
+//! Commands declare [Cooldown]s in their [CommandDetails](super::CommandDetails): how many times
+//! they can be used in a stretch of time, and who that's counted for (each user, each channel,
+//! each guild, or everyone at once). The dispatcher checks them with [Cooldowns] before running
+//! the command.
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serenity::all::{ChannelId, CommandInteraction, GuildId, UserId};
+use tokio::time::Instant;
+
+/// Who a cooldown is counted for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bucket {
+    User,
+    Channel,
+    /// Each guild. In DMs, this is each channel
+    Guild,
+    Global,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum BucketKey {
+    User(UserId),
+    Channel(ChannelId),
+    Guild(GuildId),
+    Global,
+}
+
+impl Bucket {
+    fn key(self, cmd: &CommandInteraction) -> BucketKey {
+        match (self, cmd.guild_id) {
+            (Bucket::User, _) => BucketKey::User(cmd.user.id),
+            (Bucket::Guild, Some(guild)) => BucketKey::Guild(guild),
+            (Bucket::Channel | Bucket::Guild, _) => BucketKey::Channel(cmd.channel_id),
+            (Bucket::Global, _) => BucketKey::Global,
+        }
+    }
+}
+
+/// A limit on how often a command can be used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cooldown {
+    bucket: Bucket,
+    uses: usize,
+    per: Duration,
+}
+
+impl Cooldown {
+    /// The command can be used once every `per` in each bucket
+    pub fn new(bucket: Bucket, per: Duration) -> Self {
+        Self {
+            bucket,
+            uses: 1,
+            per,
+        }
+    }
+
+    /// How many times the command can be used in each stretch of time
+    pub fn uses(mut self, uses: usize) -> Self {
+        self.uses = uses.max(1);
+        self
+    }
+}
+
+/// The last few times a command was used in one bucket
+#[derive(Debug)]
+struct Usage {
+    /// How long the cooldown lasts
+    per: Duration,
+    /// Oldest first, and only as many as the cooldown allows
+    times: VecDeque<Instant>,
+}
+
+/// When each command was last used in each bucket
+#[derive(Debug, Default)]
+pub struct Cooldowns {
+    // Keyed by command name, which of its cooldowns this is, and the bucket
+    used: Mutex<HashMap<(String, usize, BucketKey), Usage>>,
+}
+
+impl Cooldowns {
+    /// Counts a use of a command, if all of its cooldowns allow it. If not, nothing is counted
+    /// and this returns how long to wait until it can be used again.
+    pub fn try_use(
+        &self,
+        name: &str,
+        cooldowns: &[Cooldown],
+        cmd: &CommandInteraction,
+    ) -> Result<(), Duration> {
+        if cooldowns.is_empty() {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let mut used = self.used.lock().unwrap();
+
+        // Forget uses that have cooled down, so the map doesn't grow forever
+        used.retain(|_, usage| {
+            usage
+                .times
+                .back()
+                .is_some_and(|time| now.duration_since(*time) < usage.per)
+        });
+
+        let key = |i: usize, cooldown: &Cooldown| (name.to_owned(), i, cooldown.bucket.key(cmd));
+
+        // If any of them are full, wait until the oldest use cools down to make room
+        let wait = cooldowns
+            .iter()
+            .enumerate()
+            .filter_map(|(i, cooldown)| {
+                let usage = used.get(&key(i, cooldown))?;
+                let oldest = usage.times.front()?;
+                let elapsed = now.duration_since(*oldest);
+                (usage.times.len() >= cooldown.uses && elapsed < cooldown.per)
+                    .then(|| cooldown.per - elapsed)
+            })
+            .max();
+
+        if let Some(wait) = wait {
+            return Err(wait);
+        }
+
+        for (i, cooldown) in cooldowns.iter().enumerate() {
+            let usage = used.entry(key(i, cooldown)).or_insert_with(|| Usage {
+                per: cooldown.per,
+                times: VecDeque::new(),
+            });
+
+            usage.times.push_back(now);
+            while usage.times.len() > cooldown.uses {
+                usage.times.pop_front();
+            }
+        }
+
+        Ok(())
+    }
+}
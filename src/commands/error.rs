@@ -0,0 +1,145 @@
+//! Errors that can happen while running a command.
+//!
+//! Commands return these instead of just logging and giving up, so that the dispatcher can always
+//! tell the user something went wrong (rather than leaving them with discord's "The application
+//! did not respond").
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rand::Rng;
+use serenity::all::{ChannelId, Mentionable};
+use tracing::error;
+
+use crate::storage::StorageError;
+
+use super::responder::{Reply, Responder};
+
+#[derive(Debug)]
+pub enum CommandError {
+    /// There's no command by this name
+    UnknownCommand(String),
+    /// The options given to the command don't make sense
+    BadOption(String),
+    /// The user isn't allowed to use this command
+    NotAllowed,
+    /// The command has been turned off in this guild
+    Disabled,
+    /// The command can only be used in these channels in this guild
+    WrongChannel(Vec<ChannelId>),
+    /// A component was used after whatever it belongs to stopped listening for it (or it was
+    /// never known at all)
+    Expired,
+    /// The command has been used too much recently, and can be used again after this long
+    CoolingDown(Duration),
+    /// A request to discord failed
+    Discord(Box<serenity::Error>),
+    /// Something couldn't be saved or loaded
+    Storage(Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// The result of running a command
+pub type CommandResult = Result<(), CommandError>;
+
+impl CommandError {
+    /// A friendly (and in-character) explanation of what went wrong, to show to the user
+    pub fn user_message(&self) -> String {
+        match self {
+            CommandError::UnknownCommand(name) => format!(
+                "Huh? I don't know how to do \"{name}\"... I might have forgotten it. Sorry!"
+            ),
+            CommandError::BadOption(reason) => format!("Sorry! {reason}"),
+            CommandError::NotAllowed => {
+                "Sorry! Only the Professor is allowed to do that.".to_owned()
+            }
+            CommandError::Disabled => {
+                "Sorry! That's been turned off in this server.".to_owned()
+            }
+            CommandError::WrongChannel(channels) => {
+                let channels = channels
+                    .iter()
+                    .map(|channel| channel.mention().to_string())
+                    .collect::<Vec<_>>();
+                format!(
+                    "Sorry! That can only be used in {} here.",
+                    channels.join(", ")
+                )
+            }
+            CommandError::Expired => {
+                "Sorry! That's too old for me to do anything with now. Could you try the command again?".to_owned()
+            }
+            CommandError::CoolingDown(wait) => {
+                // Discord shows this as a countdown, in the user's own time zone
+                let retry_at = SystemTime::now() + *wait + Duration::from_secs(1);
+                let retry_at = retry_at.duration_since(UNIX_EPOCH).unwrap_or_default();
+                format!(
+                    "Slow down! I'm still catching my breath. You can use that again <t:{}:R>.",
+                    retry_at.as_secs()
+                )
+            }
+            CommandError::Discord(_) | CommandError::Storage(_) => {
+                "Ah! Something went wrong on my end... I'm not broken, I promise! Could you try again in a bit?".to_owned()
+            }
+        }
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::UnknownCommand(name) => write!(f, "unknown command \"{name}\""),
+            CommandError::BadOption(reason) => write!(f, "bad option: {reason}"),
+            CommandError::NotAllowed => write!(f, "user isn't allowed to use this command"),
+            CommandError::Disabled => write!(f, "command is turned off in this guild"),
+            CommandError::WrongChannel(_) => write!(f, "command can't be used in this channel"),
+            CommandError::Expired => write!(f, "component has expired"),
+            CommandError::CoolingDown(wait) => write!(f, "command is cooling down for {wait:?}"),
+            CommandError::Discord(e) => write!(f, "discord error: {e}"),
+            CommandError::Storage(e) => write!(f, "storage error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+impl From<serenity::Error> for CommandError {
+    fn from(e: serenity::Error) -> Self {
+        CommandError::Discord(Box::new(e))
+    }
+}
+
+impl From<StorageError> for CommandError {
+    fn from(e: StorageError) -> Self {
+        CommandError::Storage(Box::new(e))
+    }
+}
+
+/// Makes a short random id, so that an error message shown to a user can be matched up with the
+/// logs
+fn new_error_id() -> String {
+    format!("{:08x}", rand::thread_rng().gen::<u32>())
+}
+
+/// Logs a failed command and lets the user know what happened.
+///
+/// The message includes an error id that also appears in the logs, so a confused user can tell
+/// us which error they got.
+pub async fn report_error(responder: &Responder, e: CommandError) {
+    let error_id = new_error_id();
+    error!(error_id, "command failed: {e}");
+
+    let reply = Reply::new()
+        .content(format!("{}\n-# error id: `{error_id}`", e.user_message()))
+        .ephemeral(true);
+
+    // If the command got as far as responding, editing that response into an error message would
+    // throw it away, so send the error separately
+    let result = if responder.has_responded().await {
+        responder.followup(reply).await
+    } else {
+        responder.respond(reply).await
+    };
+
+    if let Err(e) = result {
+        error!(error_id, "couldn't send error message: {e}");
+    }
+}
@@ -1,32 +1,91 @@
 // TODO: Refactor this. It's a bit of a mishmash of broken stuff patched over
 // not the nicest code
-use std::time::Duration;
-
+use serde::Deserialize;
 use serenity::{
-    all::{
-        CommandInteraction, CommandOptionType, CreateButton, CreateEmbedFooter,
-        EditInteractionResponse, ResolvedOption, ResolvedValue,
-    },
-    builder::{
-        CreateCommand, CreateCommandOption, CreateEmbed, CreateEmbedAuthor,
-        CreateInteractionResponse, CreateInteractionResponseMessage,
-    },
-    prelude::Context,
+    all::{AutocompleteChoice, CommandInteraction, CreateEmbedFooter, CreateSelectMenuOption},
+    builder::{CreateCommand, CreateEmbed, CreateEmbedAuthor},
 };
-use tokio::sync::mpsc;
-use tracing::{error, info};
 
-use crate::utils::get_nano_icon;
+use crate::components::{create_component_fn, ComponentFn};
+use crate::discord::{Discord, DiscordApi};
 use crate::event_handler::Handler;
+use crate::utils::get_nano_icon;
 
-use super::{create_command_fn, CommandDetails};
+use super::error::{CommandError, CommandResult};
+use super::options::{command_options, CommandOptions};
+use super::paginator::{turn_page, Page, Paginator};
+use super::responder::Responder;
+use super::{create_autocomplete_fn, create_command_fn, CommandDetails};
 
 const PAGE_LENGTH: usize = 15;
 
+/// The namespace of the help message's buttons
+pub const NAMESPACE: &str = "help";
+
+/// The sections commands are sorted into in /help
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    Affection,
+    Reactions,
+    #[default]
+    Fun,
+    Utility,
+    Admin,
+}
+
+impl Category {
+    pub const ALL: [Category; 5] = [
+        Category::Affection,
+        Category::Reactions,
+        Category::Fun,
+        Category::Utility,
+        Category::Admin,
+    ];
+
+    /// The name used in assets and custom ids
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::Affection => "affection",
+            Category::Reactions => "reactions",
+            Category::Fun => "fun",
+            Category::Utility => "utility",
+            Category::Admin => "admin",
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Category::Affection => "Affection",
+            Category::Reactions => "Reactions",
+            Category::Fun => "Fun",
+            Category::Utility => "Utility",
+            Category::Admin => "Admin",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Category::Affection => "Show someone you care",
+            Category::Reactions => "Let everyone know how you feel",
+            Category::Fun => "Silly things to do to your friends (and Nano)",
+            Category::Utility => "Getting around Nano",
+            Category::Admin => "Looking after Nano",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|category| category.name() == name)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct HelpDetails {
     pub name: String,
     pub details: String,
+    pub category: Category,
     pub sub_commands: Vec<HelpDetails>,
 }
 impl Default for HelpDetails {
@@ -34,354 +93,508 @@ impl Default for HelpDetails {
         Self {
             name: "default command".to_string(),
             details: "default text".to_string(),
+            category: Category::default(),
             sub_commands: Vec::new(),
         }
     }
 }
 
-struct Buttons {
-    back: CreateButton,
-    accept: CreateButton,
-    forward: CreateButton,
-}
-
-fn create_buttons(commands: usize, page: usize) -> Buttons {
-    let total_pages = (commands as f32 / PAGE_LENGTH as f32).ceil() as usize;
-    let mut back = CreateButton::new("back").label("←");
-    let accept = CreateButton::new("accept").label("✓");
-    let mut forward = CreateButton::new("forward").label("→");
+async fn nano_author(discord: &dyn DiscordApi) -> CreateEmbedAuthor {
+    let author =
+        CreateEmbedAuthor::new("Nano (not a bot)").url("https://github.com/villuna/nano-bot");
 
-    if page == 0 {
-        back = back.disabled(true)
+    match get_nano_icon(discord).await {
+        Some(icon) => author.icon_url(icon),
+        None => author,
     }
+}
 
-    if page >= total_pages - 1 {
-        forward = forward.disabled(true);
-    }
+/// The commands a help message lists, and what to call them
+struct Listing<'a> {
+    title: String,
+    /// Shown above the commands
+    intro: Option<String>,
+    /// Each command, with its full name (including the command it's a subcommand of)
+    commands: Vec<(String, &'a HelpDetails)>,
+}
 
-    Buttons {
-        back,
-        accept,
-        forward,
+impl Listing<'_> {
+    fn page_count(&self) -> usize {
+        self.commands.len().div_ceil(PAGE_LENGTH).max(1)
     }
 }
 
-async fn create_subcommand_help_message(
-    ctx: &Context,
-    details: &HelpDetails,
-    page: usize,
-) -> (CreateEmbed, Buttons) {
-    let mut embed = CreateEmbed::new()
-        .author(
-            CreateEmbedAuthor::new("Nano (not a bot)")
-                .icon_url(get_nano_icon(ctx).await)
-                .url("https://github.com/villuna/nano-bot"),
-        )
-        .title(format!("Subcommands for {}:", details.name));
-
-    embed = embed.field(
-        "",
-        format!("**{}: {}**", details.name, details.details),
-        false,
-    );
-    // vertical spacing
-    embed = embed.field("", "", false);
-    embed = add_embed_page(embed, &details.sub_commands, Some(&details.name), page);
-    let buttons = create_buttons(details.sub_commands.len(), page);
-    (embed, buttons)
-}
+fn listing<'a>(
+    data: &'a [HelpDetails],
+    options: &HelpCommandOptions,
+) -> Result<Listing<'a>, CommandError> {
+    let top_level = |details: &'a HelpDetails| (details.name.clone(), details);
+
+    let listing = match options {
+        HelpCommandOptions::AllCommands => Listing {
+            title: "Here are all the commands I can perform:".to_owned(),
+            intro: None,
+            commands: data.iter().map(top_level).collect(),
+        },
+
+        HelpCommandOptions::Category(category) => Listing {
+            title: format!("{} commands:", category.title()),
+            intro: Some(format!("*{}*", category.description())),
+            commands: data
+                .iter()
+                .filter(|details| details.category == *category)
+                .map(top_level)
+                .collect(),
+        },
 
-async fn create_help_message(
-    ctx: &Context,
-    details: &[HelpDetails],
-    page: usize,
-) -> (CreateEmbed, Buttons) {
-    let mut embed = CreateEmbed::new()
-        .author(
-            CreateEmbedAuthor::new("Nano (not a bot)")
-                .icon_url(get_nano_icon(ctx).await)
-                .url("https://github.com/villuna/nano-bot"),
-        )
-        .title("Here are all the commands I can perform:");
-
-    embed = add_embed_page(embed, details, None, page);
-    let buttons = create_buttons(details.len(), page);
-    (embed, buttons)
+        HelpCommandOptions::SubCommand(name) => {
+            let details = data
+                .iter()
+                .find(|deets| &deets.name == name)
+                .ok_or_else(|| not_found(name.to_owned()))?;
+
+            if details.sub_commands.is_empty() {
+                return Ok(Listing {
+                    title: format!("Help for /{}:", details.name),
+                    intro: None,
+                    commands: vec![top_level(details)],
+                });
+            }
+
+            Listing {
+                title: format!("Subcommands for {}:", details.name),
+                intro: Some(format!("**{}: {}**", details.name, details.details)),
+                commands: subcommands(&details.name, details),
+            }
+        }
+
+        HelpCommandOptions::Search(query) => {
+            let commands = search(data, query);
+            if commands.is_empty() {
+                return Err(CommandError::BadOption(format!(
+                    "I couldn't find any commands like \"{query}\""
+                )));
+            }
+
+            Listing {
+                title: format!("Commands like \"{query}\":"),
+                intro: None,
+                commands,
+            }
+        }
+    };
+
+    Ok(listing)
 }
 
-fn add_embed_page(
-    mut embed: CreateEmbed,
-    details: &[HelpDetails],
-    prefix: Option<&str>,
+async fn create_message(
+    discord: &dyn DiscordApi,
+    listing: &Listing<'_>,
     page: usize,
 ) -> CreateEmbed {
-    let prefix = prefix.map(|s| format!("{s} ")).unwrap_or_default();
+    let mut embed = CreateEmbed::new()
+        .author(nano_author(discord).await)
+        .title(&listing.title);
 
-    for command in details.iter().skip(PAGE_LENGTH * page).take(PAGE_LENGTH) {
-        let subcommand_text = (!command.sub_commands.is_empty())
-            .then(|| format!(" `(see /help {})`", command.name))
-            .unwrap_or("".to_owned());
+    if let Some(intro) = &listing.intro {
+        embed = embed.field("", intro, false);
+        // vertical spacing
+        embed = embed.field("", "", false);
+    }
+
+    for (name, command) in listing
+        .commands
+        .iter()
+        .skip(PAGE_LENGTH * page)
+        .take(PAGE_LENGTH)
+    {
+        let subcommand_text = if command.sub_commands.is_empty() {
+            String::new()
+        } else {
+            format!(" `(see /help {})`", command.name)
+        };
 
         embed = embed.field(
             "",
-            format!(
-                "/**{}{}**: {}{}",
-                prefix, command.name, command.details, subcommand_text
-            ),
+            format!("/**{}**: {}{}", name, command.details, subcommand_text),
             false,
         );
     }
 
-    let total_pages = (details.len() as f32 / PAGE_LENGTH as f32).ceil();
-    embed = embed.footer(CreateEmbedFooter::new(format!(
+    embed.footer(CreateEmbedFooter::new(format!(
         "Page {}/{}",
         page + 1,
-        total_pages
-    )));
-    embed
+        listing.page_count()
+    )))
 }
 
-async fn create_message(
-    ctx: &Context,
+/// The select menu for jumping between categories. Only categories with commands in them are
+/// shown.
+fn category_menu(
     data: &[HelpDetails],
     options: &HelpCommandOptions,
-    page: usize,
-) -> Result<(CreateEmbed, Buttons), String> {
-    match options {
-        HelpCommandOptions::AllCommands => Ok(create_help_message(ctx, data, page).await),
+) -> Vec<CreateSelectMenuOption> {
+    let all =
+        CreateSelectMenuOption::new("All commands", HelpCommandOptions::AllCommands.to_state())
+            .description("Everything I can do")
+            .default_selection(matches!(options, HelpCommandOptions::AllCommands));
+
+    let categories = Category::ALL
+        .into_iter()
+        .filter(|category| data.iter().any(|details| details.category == *category))
+        .map(|category| {
+            let state = HelpCommandOptions::Category(category).to_state();
+            CreateSelectMenuOption::new(category.title(), state)
+                .description(category.description())
+                .default_selection(
+                    matches!(options, HelpCommandOptions::Category(c) if *c == category),
+                )
+        });
+
+    std::iter::once(all).chain(categories).collect()
+}
 
-        HelpCommandOptions::SubCommand(name) => {
-            match data.iter().find(|deets| &deets.name == name) {
-                None => Err(name.to_owned()),
-
-                Some(
-                    details @ HelpDetails {
-                        name, sub_commands, ..
-                    },
-                ) => {
-                    if sub_commands.is_empty() {
-                        Err(name.to_owned())
-                    } else {
-                        Ok(create_subcommand_help_message(ctx, details, page).await)
-                    }
-                }
+/// The subcommands that can actually be used, with their full names. Groups of subcommands are
+/// replaced with what's in them.
+fn subcommands<'a>(prefix: &str, details: &'a HelpDetails) -> Vec<(String, &'a HelpDetails)> {
+    details
+        .sub_commands
+        .iter()
+        .flat_map(|sub| {
+            let name = format!("{prefix} {}", sub.name);
+            if sub.sub_commands.is_empty() {
+                vec![(name, sub)]
+            } else {
+                subcommands(&name, sub)
+            }
+        })
+        .collect()
+}
+
+/// Every command and subcommand that looks like `query`, best match first
+fn search<'a>(data: &'a [HelpDetails], query: &str) -> Vec<(String, &'a HelpDetails)> {
+    let query = query.trim().to_lowercase();
+
+    let commands = data.iter().flat_map(|details| {
+        let subs = subcommands(&details.name, details);
+        std::iter::once((details.name.clone(), details)).chain(subs)
+    });
+
+    let mut ranked = commands
+        .filter_map(|(name, details)| {
+            let score = search_score(&query, &name, &details.details)?;
+            Some((score, name, details))
+        })
+        .collect::<Vec<_>>();
+
+    ranked.sort_by(|(a, a_name, _), (b, b_name, _)| b.cmp(a).then_with(|| a_name.cmp(b_name)));
+    ranked
+        .into_iter()
+        .map(|(_, name, details)| (name, details))
+        .collect()
+}
+
+/// How well a command matches a (lowercase) search query, or None if it doesn't at all. Matches on
+/// the name count for much more than matches in the description, and small typos are forgiven.
+fn search_score(query: &str, name: &str, description: &str) -> Option<u32> {
+    if query.is_empty() {
+        return None;
+    }
+
+    let name_score = if name == query {
+        1000
+    } else if name.starts_with(query) {
+        800
+    } else if name.contains(query) {
+        600
+    } else if let Some(gaps) = subsequence_gaps(query, name) {
+        400u32.saturating_sub(gaps as u32 * 20).max(200)
+    } else {
+        // Allow about one typo for every three letters
+        let distance = edit_distance(query, name);
+        if distance <= (query.chars().count() / 3).max(1) {
+            300 - distance as u32 * 50
+        } else {
+            0
+        }
+    };
+
+    let description = description.to_lowercase();
+    let description_score = query
+        .split_whitespace()
+        .filter(|word| description.contains(word))
+        .count() as u32
+        * 100;
+
+    match name_score + description_score {
+        0 => None,
+        score => Some(score),
+    }
+}
+
+/// If every letter of `query` appears in `text` in order, how many letters were skipped between
+/// them
+fn subsequence_gaps(query: &str, text: &str) -> Option<usize> {
+    let mut text = text.chars();
+    let mut gaps = 0;
+    let mut started = false;
+
+    for c in query.chars() {
+        loop {
+            match text.next() {
+                Some(t) if t == c => break,
+                Some(_) if started => gaps += 1,
+                Some(_) => {}
+                None => return None,
+            }
+        }
+        started = true;
+    }
+
+    Some(gaps)
+}
+
+/// The number of single letter changes (adding, removing or changing a letter, or swapping two
+/// next to each other) to get from one string to another
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b) = (a.chars().collect::<Vec<_>>(), b.chars().collect::<Vec<_>>());
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = distances[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = substitution
+                .min(distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
             }
+
+            distances[i][j] = distance;
         }
     }
+
+    distances[a.len()][b.len()]
 }
 
 enum HelpCommandOptions {
     AllCommands,
+    Category(Category),
     SubCommand(String),
+    Search(String),
 }
 
-fn page_count(data: &[HelpDetails], options: &HelpCommandOptions) -> Option<usize> {
-    let commands = match options {
-        HelpCommandOptions::AllCommands => Some(data.len()),
-        HelpCommandOptions::SubCommand(name) => data
-            .iter()
-            .find(|deets| &deets.name == name)
-            .and_then(|details| {
-                if details.sub_commands.is_empty() {
-                    None
-                } else {
-                    Some(details.sub_commands.len())
-                }
-            }),
-    }?;
-
-    Some((commands as f32 / PAGE_LENGTH as f32).ceil() as _)
+/// Search queries are kept in the buttons' custom ids, which can't be very long. This is in
+/// characters, but what fits in a custom id is counted in bytes, so queries are cut down again
+/// by [HelpCommandOptions::fit] before they're used
+const MAX_QUERY_LENGTH: u16 = 40;
+
+command_options! {
+    struct HelpOptions {
+        /// an (optional) command to get detailed info about
+        command: Option<String> => set_autocomplete(true),
+        /// search for commands by name or description
+        query: Option<String> => max_length(MAX_QUERY_LENGTH),
+    }
 }
 
-impl HelpCommandOptions {
-    fn parse(options: &[ResolvedOption<'_>]) -> Self {
-        if options.is_empty() {
-            Self::AllCommands
-        } else {
-            let ResolvedValue::String(name) = options[0].value else {
-                // should be unreachable
-                panic!("argument to help command is of incorrect type");
-            };
+impl From<HelpOptions> for HelpCommandOptions {
+    fn from(options: HelpOptions) -> Self {
+        match options {
+            HelpOptions {
+                command: Some(name),
+                ..
+            } => Self::SubCommand(name),
+
+            HelpOptions {
+                query: Some(query), ..
+            } => Self::Search(query.trim().to_owned()),
 
-            Self::SubCommand(name.to_owned())
+            _ => Self::AllCommands,
         }
     }
 }
 
-pub async fn run(ctx: Context, cmd: &CommandInteraction, handler: Handler) {
-    let options = cmd.data.options();
-    let options = HelpCommandOptions::parse(&options);
+impl HelpCommandOptions {
+    /// The options, to be kept in the help message's buttons. All commands is `*`, categories
+    /// start with `#` and searches with `?`, which command names can't. It's never empty, since
+    /// discord doesn't allow select menu options with no value.
+    fn to_state(&self) -> String {
+        match self {
+            Self::AllCommands => "*".to_owned(),
+            Self::Category(category) => format!("#{}", category.name()),
+            Self::SubCommand(name) => name.clone(),
+            Self::Search(query) => format!("?{query}"),
+        }
+    }
 
-    let send_error_message = |http, name: String| async move {
-        let message = CreateInteractionResponseMessage::new()
-            .content(format!(
-                "Sorry! I couldn't find a help page for the command \"{name}\""
-            ))
-            .ephemeral(true);
+    /// Cuts a search query down so its state is no more than `budget` bytes, without splitting a
+    /// character
+    fn fit(self, budget: usize) -> Self {
+        let Self::Search(mut query) = self else {
+            return self;
+        };
 
-        let response = CreateInteractionResponse::Message(message);
+        // The state has a `?` before the query
+        let mut length = budget.saturating_sub(1);
+        while !query.is_char_boundary(length.min(query.len())) {
+            length -= 1;
+        }
+        query.truncate(length);
+
+        Self::Search(query)
+    }
 
-        if let Err(e) = cmd.create_response(http, response).await {
-            error!("error sending error response: {e}");
+    fn from_state(state: String) -> Self {
+        if state == "*" {
+            return Self::AllCommands;
         }
-    };
 
-    let mut page = 0;
-    let (embed, buttons) =
-        match create_message(&ctx, &handler.help_data.read().await, &options, page).await {
-            Ok(res) => res,
-            Err(name) => {
-                send_error_message(&ctx.http, name).await;
-                return;
-            }
-        };
+        if let Some(query) = state.strip_prefix('?') {
+            return Self::Search(query.to_owned());
+        }
 
-    let message = CreateInteractionResponseMessage::new()
-        .embed(embed)
-        .button(buttons.back)
-        .button(buttons.accept)
-        .button(buttons.forward);
+        match state.strip_prefix('#').and_then(Category::from_name) {
+            Some(category) => Self::Category(category),
+            None => Self::SubCommand(state),
+        }
+    }
+}
 
-    let total_pages = page_count(&handler.help_data.read().await, &options).unwrap();
+fn not_found(name: String) -> CommandError {
+    CommandError::BadOption(format!(
+        "I couldn't find a help page for the command \"{name}\""
+    ))
+}
 
-    let response = CreateInteractionResponse::Message(message);
+/// Renders a page of help from scratch, so any instance of nano can do it at any time
+async fn render_page(
+    discord: &dyn DiscordApi,
+    handler: &Handler,
+    options: &HelpCommandOptions,
+    page: usize,
+) -> Result<Page, CommandError> {
+    let data = handler.help_data.read().await;
+    let listing = listing(&data, options)?;
+    let embed = create_message(discord, &listing, page).await;
 
-    if let Err(e) = cmd.create_response(&ctx.http, response).await {
-        error!("error sending response to help command: {e}");
-        return;
-    }
+    Ok(Page::new(embed, listing.page_count())
+        .menu("Jump to a category", category_menu(&data, options)))
+}
 
-    let id = cmd.get_response(&ctx.http).await.unwrap().id;
-
-    // Create a channel through which the event handler will send button events
-    let (tx, mut rx) = mpsc::channel(256);
-    handler.button_event_tx.write().await.insert(id, tx);
-
-    // This future loops forever, recieving button interactions and updating the help message
-    // accordingly. We use a timeout later to cap the running time of this loop.
-    let recv_loop = async {
-        'mainloop: loop {
-            match rx.recv().await {
-                None => {
-                    error!("channel closed unexpectedly!");
-                    break 'mainloop;
-                }
-
-                Some(interaction) if interaction.user.id == cmd.user.id => {
-                    info!("recieved button interaction");
-                    match interaction.data.custom_id.as_str() {
-                        "back" => {
-                            page = page.saturating_sub(1);
-                        }
-
-                        "forward" => {
-                            if page < total_pages {
-                                page += 1;
-                            }
-                        }
-
-                        "accept" => {
-                            break 'mainloop;
-                        }
-
-                        s => {
-                            error!("invalid button event recieved: \"{}\"! did you forget to handle it?", s);
-                        }
-                    }
-
-                    let (embed, buttons) =
-                        match create_message(&ctx, &handler.help_data.read().await, &options, page)
-                            .await
-                        {
-                            Ok(res) => res,
-                            Err(name) => {
-                                send_error_message(&ctx.http, name).await;
-                                return;
-                            }
-                        };
-
-                    let response = EditInteractionResponse::new()
-                        .embed(embed)
-                        .button(buttons.back)
-                        .button(buttons.accept)
-                        .button(buttons.forward);
-
-                    if let Err(e) = cmd.edit_response(&ctx.http, response).await {
-                        error!("error sending response to help command: {e}");
-                        return;
-                    }
-
-                    let acknowledge = CreateInteractionResponse::Acknowledge;
-                    if let Err(e) = interaction.create_response(&ctx.http, acknowledge).await {
-                        error!("error acknowledging button interaction: {e}");
-                        return;
-                    }
-                }
-
-                Some(interaction) => {
-                    let acknowledge = CreateInteractionResponse::Acknowledge;
-                    if let Err(e) = interaction.create_response(&ctx.http, acknowledge).await {
-                        error!("error acknowledging button interaction from wrong user: {e}");
-                        return;
-                    }
-                }
-            }
-        }
-    };
+pub async fn run(
+    discord: Discord,
+    cmd: &CommandInteraction,
+    handler: Handler,
+    responder: Responder,
+) -> CommandResult {
+    let budget = Paginator::new(NAMESPACE, cmd.user.id, "").state_budget();
+    let options = HelpCommandOptions::from(HelpOptions::parse(&cmd.data.options())?).fit(budget);
 
-    // Create a timer and only run the previous loop until the timer dings
-    let timeout = tokio::time::sleep(Duration::from_secs(60));
-    tokio::select! {
-        _ = timeout => {},
-        _ = recv_loop => {},
-    }
+    let page = render_page(&*discord, &handler, &options, 0).await?;
+    let paginator = Paginator::new(NAMESPACE, cmd.user.id, options.to_state());
+    responder.respond(paginator.reply(0, page)).await?;
 
-    // Now we can remove the channel and update the message to have no buttons.
-    info!("shutting down handler thread for help message");
-    handler.button_event_tx.write().await.remove(&id);
+    Ok(())
+}
 
-    let (embed, _) =
-        match create_message(&ctx, &handler.help_data.read().await, &options, page).await {
-            Ok(res) => res,
-            Err(name) => {
-                send_error_message(&ctx.http, name).await;
-                return;
+/// Turns the pages of help messages
+pub fn components() -> ComponentFn {
+    create_component_fn(|discord, handler, event, id| async move {
+        let render = |state, page| {
+            let (discord, handler) = (&discord, &handler);
+            async move {
+                let options = HelpCommandOptions::from_state(state);
+                render_page(&**discord, handler, &options, page).await
             }
         };
 
-    let response = EditInteractionResponse::new()
-        .components(Vec::new())
-        .embed(embed);
+        turn_page(&discord, &event, &id, render).await
+    })
+}
 
-    if let Err(e) = cmd.edit_response(&ctx.http, response).await {
-        error!("error updating help command: {e}");
+/// Suggests commands for the `command` option, as the user types it
+async fn autocomplete(handler: Handler, cmd: &CommandInteraction) -> Vec<AutocompleteChoice> {
+    let Some(focused) = cmd.data.autocomplete() else {
+        return Vec::new();
+    };
+
+    if focused.name != "command" {
+        return Vec::new();
     }
+
+    let data = handler.help_data.read().await;
+    suggest_commands(&data, focused.value, |_| true)
+}
+
+/// Top level commands that match what the user has typed so far, best first. Only commands that
+/// `keep` returns true for are suggested
+pub fn suggest_commands(
+    data: &[HelpDetails],
+    typed: &str,
+    keep: impl Fn(&HelpDetails) -> bool,
+) -> Vec<AutocompleteChoice> {
+    let commands = if typed.trim().is_empty() {
+        data.iter().collect::<Vec<_>>()
+    } else {
+        // Subcommands can't be looked up on their own, so only suggest whole commands
+        search(data, typed)
+            .into_iter()
+            .filter(|(name, _)| !name.contains(' '))
+            .map(|(_, details)| details)
+            .collect()
+    };
+
+    commands
+        .into_iter()
+        .filter(|details| keep(details))
+        .map(|details| {
+            let mut label = format!("{}: {}", details.name, details.details);
+            if label.chars().count() > 100 {
+                label = label.chars().take(99).collect::<String>() + "…";
+            }
+
+            AutocompleteChoice::new(label, details.name.clone())
+        })
+        .collect()
 }
 
 pub fn register() -> CommandDetails {
     let registration = CreateCommand::new("help")
         .description("A list of the commands that can be used")
-        .add_option(CreateCommandOption::new(
-            CommandOptionType::String,
-            "command",
-            "an (optional) command to get detailed info about",
-        ));
+        .set_options(HelpOptions::options());
 
     let help = HelpDetails {
         name: "help".to_string(),
         details:
-            "Get help on what commands can be used. You can also query information on commands that have sub commands."
+            "Get help on what commands can be used. You can also look up a command (and its sub commands), or search for commands."
                 .to_string(),
+        category: Category::Utility,
         sub_commands: Vec::new(),
     };
 
-    let command =
-        create_command_fn(|ctx, handler, cmd| async move { run(ctx, &cmd, handler).await });
+    let command = create_command_fn(|discord, handler, cmd, responder| async move {
+        run(discord, &cmd, handler, responder).await
+    });
+
+    let autocomplete = create_autocomplete_fn(|_discord, handler, cmd| async move {
+        autocomplete(handler, &cmd).await
+    });
 
     CommandDetails {
         name: "help".to_owned(),
         registration,
         help,
         command,
+        autocomplete: Some(autocomplete),
+        cooldowns: Vec::new(),
     }
 }
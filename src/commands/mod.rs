@@ -1,43 +1,86 @@
-use std::pin::Pin;
 use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
 
-use serenity::{all::CommandInteraction, builder::CreateCommand, prelude::Context};
+use serenity::{
+    all::{AutocompleteChoice, CommandInteraction},
+    builder::CreateCommand,
+};
 
 pub mod action;
+pub mod config;
+pub mod cooldown;
+pub mod error;
 pub mod help;
+pub mod options;
+pub mod paginator;
+pub mod reload;
+pub mod responder;
 pub mod say_hi;
+pub mod stats;
+pub mod subcommands;
 
+use cooldown::Cooldown;
+use error::CommandResult;
 use help::HelpDetails;
+use responder::Responder;
 
+use crate::components::ComponentRouter;
+use crate::discord::Discord;
 use crate::event_handler::Handler;
 
 // pwetty pleeeeeeeease stablise async closures :3333
 
 /// An async function that performs a command response.
 ///
-/// Takes in a [Context], [Handler] and
-/// [CommandInteraction] and returns a future with no result.
+/// Takes in a handle to [Discord], the [Handler], [CommandInteraction] and the [Responder] to
+/// send the response with, and returns a future with a [CommandResult]. If the command fails, the dispatcher lets
+/// the user know.
 ///
-/// This is a boxed trait object which returns boxed trait object. To create one from a regular
+/// This is a shared trait object which returns boxed trait object. To create one from a regular
 /// closure, use [create_command_fn]
-pub type CommandFn = Box<
+pub type CommandFn = Arc<
     dyn Fn(
-            Context,
+            Discord,
             Handler,
             CommandInteraction,
-        ) -> Pin<Box<dyn Future<Output = ()> + Send>>
+            Responder,
+        ) -> Pin<Box<dyn Future<Output = CommandResult> + Send>>
         + Send
         + Sync,
 >;
 
-/// Takes in a function that returns a future, and turns it into a boxed function that returns a
+/// Takes in a function that returns a future, and turns it into a shared function that returns a
 /// boxed future. Useful so we can store a bunch of type-erased async closures together.
 pub fn create_command_fn<F, R>(f: F) -> CommandFn
 where
-    F: Fn(Context, Handler, CommandInteraction) -> R + Send + Sync + 'static,
-    R: Future<Output = ()> + Send + 'static,
+    F: Fn(Discord, Handler, CommandInteraction, Responder) -> R + Send + Sync + 'static,
+    R: Future<Output = CommandResult> + Send + 'static,
+{
+    Arc::new(move |discord, handler, cmd, responder| Box::pin(f(discord, handler, cmd, responder)))
+}
+
+/// An async function that suggests values for the option a user is typing in. Like a
+/// [CommandFn], but it gets an autocomplete interaction, which says which option is focused.
+///
+/// To create one from a regular closure, use [create_autocomplete_fn]
+pub type AutocompleteFn = Arc<
+    dyn Fn(
+            Discord,
+            Handler,
+            CommandInteraction,
+        ) -> Pin<Box<dyn Future<Output = Vec<AutocompleteChoice>> + Send>>
+        + Send
+        + Sync,
+>;
+
+/// Turns an async function into an [AutocompleteFn]
+pub fn create_autocomplete_fn<F, R>(f: F) -> AutocompleteFn
+where
+    F: Fn(Discord, Handler, CommandInteraction) -> R + Send + Sync + 'static,
+    R: Future<Output = Vec<AutocompleteChoice>> + Send + 'static,
 {
-    Box::new(move |ctx, handler, cmd| Box::pin(f(ctx, handler, cmd)))
+    Arc::new(move |discord, handler, cmd| Box::pin(f(discord, handler, cmd)))
 }
 
 pub struct CommandDetails {
@@ -45,4 +88,28 @@ pub struct CommandDetails {
     pub registration: CreateCommand,
     pub help: HelpDetails,
     pub command: CommandFn,
+    /// Suggestions for the command's autocomplete options, if it has any
+    pub autocomplete: Option<AutocompleteFn>,
+    /// Limits on how often the command can be used. They all have to allow it for it to run
+    pub cooldowns: Vec<Cooldown>,
+}
+
+/// Every command that isn't an action. Actions can't have the same names as these
+pub fn builtin() -> Vec<CommandDetails> {
+    vec![
+        help::register(),
+        say_hi::register(),
+        reload::register(),
+        config::register(),
+        stats::register(),
+    ]
+}
+
+/// Makes a router which knows about the components every command uses
+pub fn component_router() -> ComponentRouter {
+    let mut router = ComponentRouter::new();
+    router.register(action::NAMESPACE, action::components());
+    router.register(help::NAMESPACE, help::components());
+    router.register(stats::NAMESPACE, stats::components());
+    router
 }
@@ -0,0 +1,205 @@
+//! Typed slash command options.
+//!
+//! Rather than digging through `cmd.data.options()` by hand, a command declares its options as a
+//! struct with [command_options!]. That gives it the options to register, and a
+//! [CommandOptions::parse] which fills in the struct, turning anything unexpected into an error
+//! the user can read.
+//!
+//! ```ignore
+//! command_options! {
+//!     struct PatOptions {
+//!         /// who to pat
+//!         target: User,
+//!         /// how many times (1-10)
+//!         times: Option<i64> => min_int_value(1).max_int_value(10),
+//!     }
+//! }
+//! ```
+//!
+//! Each field's doc comment is the option's description, and it's required unless it's an
+//! [Option]. Anything after `=>` is called on the option's [CreateCommandOption], for choices,
+//! ranges, autocomplete and so on.
+use serenity::all::{
+    CommandOptionType, CreateCommandOption, PartialChannel, ResolvedOption, ResolvedValue, User,
+};
+
+use super::error::CommandError;
+
+/// A struct of slash command options. Use [command_options!] to make one.
+pub trait CommandOptions: Sized {
+    /// The options to register the command with
+    fn options() -> Vec<CreateCommandOption>;
+
+    /// Reads the options the command was used with
+    fn parse(options: &[ResolvedOption<'_>]) -> Result<Self, CommandError>;
+}
+
+/// Something that can be read from a single option
+pub trait OptionValue: Sized {
+    const KIND: CommandOptionType;
+    const REQUIRED: bool = true;
+    /// What the value should be, to tell the user if it isn't (e.g. "a user")
+    const EXPECTED: &'static str;
+
+    fn from_resolved(value: &ResolvedValue<'_>) -> Option<Self>;
+
+    /// The value of an option, or None if it wasn't given
+    fn parse(name: &str, value: Option<&ResolvedValue<'_>>) -> Result<Self, CommandError> {
+        match value {
+            Some(value) => Self::from_resolved(value).ok_or_else(|| {
+                CommandError::BadOption(format!("\"{name}\" needs to be {}!", Self::EXPECTED))
+            }),
+            None => Err(CommandError::BadOption(format!(
+                "I need {} for \"{name}\"!",
+                Self::EXPECTED
+            ))),
+        }
+    }
+}
+
+impl OptionValue for String {
+    const KIND: CommandOptionType = CommandOptionType::String;
+    const EXPECTED: &'static str = "some text";
+
+    fn from_resolved(value: &ResolvedValue<'_>) -> Option<Self> {
+        match value {
+            ResolvedValue::String(s) => Some(s.to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl OptionValue for i64 {
+    const KIND: CommandOptionType = CommandOptionType::Integer;
+    const EXPECTED: &'static str = "a whole number";
+
+    fn from_resolved(value: &ResolvedValue<'_>) -> Option<Self> {
+        match value {
+            ResolvedValue::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+}
+
+impl OptionValue for f64 {
+    const KIND: CommandOptionType = CommandOptionType::Number;
+    const EXPECTED: &'static str = "a number";
+
+    fn from_resolved(value: &ResolvedValue<'_>) -> Option<Self> {
+        match value {
+            ResolvedValue::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+impl OptionValue for bool {
+    const KIND: CommandOptionType = CommandOptionType::Boolean;
+    const EXPECTED: &'static str = "yes or no";
+
+    fn from_resolved(value: &ResolvedValue<'_>) -> Option<Self> {
+        match value {
+            ResolvedValue::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+impl OptionValue for User {
+    const KIND: CommandOptionType = CommandOptionType::User;
+    const EXPECTED: &'static str = "a user";
+
+    fn from_resolved(value: &ResolvedValue<'_>) -> Option<Self> {
+        match value {
+            ResolvedValue::User(user, _) => Some((*user).clone()),
+            _ => None,
+        }
+    }
+}
+
+impl OptionValue for PartialChannel {
+    const KIND: CommandOptionType = CommandOptionType::Channel;
+    const EXPECTED: &'static str = "a channel";
+
+    fn from_resolved(value: &ResolvedValue<'_>) -> Option<Self> {
+        match value {
+            ResolvedValue::Channel(channel) => Some((*channel).clone()),
+            _ => None,
+        }
+    }
+}
+
+impl<T: OptionValue> OptionValue for Option<T> {
+    const KIND: CommandOptionType = T::KIND;
+    const REQUIRED: bool = false;
+    const EXPECTED: &'static str = T::EXPECTED;
+
+    fn from_resolved(value: &ResolvedValue<'_>) -> Option<Self> {
+        T::from_resolved(value).map(Some)
+    }
+
+    fn parse(name: &str, value: Option<&ResolvedValue<'_>>) -> Result<Self, CommandError> {
+        match value {
+            Some(value) => T::parse(name, Some(value)).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Reads the option called `name`. Used by [command_options!]
+pub fn parse_option<T: OptionValue>(
+    options: &[ResolvedOption<'_>],
+    name: &str,
+) -> Result<T, CommandError> {
+    let value = options
+        .iter()
+        .find(|option| option.name == name)
+        .map(|option| &option.value);
+
+    T::parse(name, value)
+}
+
+/// Declares a struct of slash command options, and implements [CommandOptions] for it. See the
+/// [module docs](self) for what it looks like.
+macro_rules! command_options {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                #[doc = $description:literal]
+                $field:ident: $ty:ty $(=> $($method:ident($($arg:expr),*)).+)?,
+            )*
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $(pub $field: $ty,)*
+        }
+
+        impl $crate::commands::options::CommandOptions for $name {
+            fn options() -> Vec<::serenity::all::CreateCommandOption> {
+                use $crate::commands::options::OptionValue;
+
+                vec![$(
+                    ::serenity::all::CreateCommandOption::new(
+                        <$ty as OptionValue>::KIND,
+                        stringify!($field),
+                        $description.trim(),
+                    )
+                    .required(<$ty as OptionValue>::REQUIRED)
+                    $($(.$method($($arg),*))+)?
+                ),*]
+            }
+
+            fn parse(
+                options: &[::serenity::all::ResolvedOption<'_>],
+            ) -> Result<Self, $crate::commands::error::CommandError> {
+                Ok(Self {
+                    $($field: $crate::commands::options::parse_option(options, stringify!($field))?,)*
+                })
+            }
+        }
+    };
+}
+
+pub(crate) use command_options;
@@ -0,0 +1,310 @@
+//! A message with buttons for flicking through pages of something.
+//!
+//! Paginators don't keep anything running while they wait for their buttons. Everything needed to
+//! show a page (who it belongs to, which page it's on and what it's a list of) is kept in the
+//! buttons' custom ids, so when one is pressed the page is just rendered again from scratch. That
+//! means the buttons keep working after nano restarts, and any instance of her can handle them.
+//!
+//! A command using a paginator sends the first page with [Paginator::reply], and registers a
+//! component handler for its namespace which calls [turn_page] with the same render function.
+use std::future::Future;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serenity::all::{
+    ActionRowComponent, ButtonStyle, ComponentInteractionDataKind, CreateActionRow, CreateButton,
+    CreateEmbed, CreateInputText, CreateInteractionResponse, CreateInteractionResponseMessage,
+    CreateModal, CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption, InputTextStyle,
+    UserId,
+};
+use tracing::{info, warn};
+
+use crate::components::{ComponentEvent, CustomId, MAX_CUSTOM_ID_LENGTH};
+use crate::discord::Discord;
+
+use super::error::{CommandError, CommandResult};
+use super::responder::Reply;
+
+/// The id of the text box in the jump to page modal
+const PAGE_INPUT: &str = "page";
+
+/// The longest action in a paginator's custom ids
+const LONGEST_ACTION: &str = "forward";
+
+/// A rendered page, and how many pages there are altogether
+pub struct Page {
+    pub embed: CreateEmbed,
+    pub total: usize,
+    menu: Option<(String, Vec<CreateSelectMenuOption>)>,
+}
+
+impl Page {
+    pub fn new(embed: CreateEmbed, total: usize) -> Self {
+        Self {
+            embed,
+            total,
+            menu: None,
+        }
+    }
+
+    /// Adds a select menu for switching to something else entirely. The value of each option is
+    /// the state to switch to, and picking one goes to the first page of it.
+    pub fn menu(
+        mut self,
+        placeholder: impl Into<String>,
+        options: Vec<CreateSelectMenuOption>,
+    ) -> Self {
+        self.menu = Some((placeholder.into(), options));
+        self
+    }
+}
+
+/// Everything about a paginated message that has to survive between button presses.
+///
+/// This is encoded into each button's custom id as `namespace:action:owner:expiry:page:state`,
+/// where `page` is the page the button goes to. An owner of 0 means anyone can use the buttons,
+/// and an expiry of 0 means they never expire.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Paginator {
+    namespace: String,
+    owner: Option<UserId>,
+    /// When the buttons stop working, in seconds since the unix epoch
+    expires: Option<u64>,
+    /// Whatever the command needs to know to render its pages, like a search query
+    state: String,
+}
+
+impl Paginator {
+    /// A paginator whose buttons are handled in `namespace`. Only `owner` can turn the pages,
+    /// unless it's [shared](Paginator::shared).
+    pub fn new(namespace: impl Into<String>, owner: UserId, state: impl Into<String>) -> Self {
+        Self {
+            namespace: namespace.into(),
+            owner: Some(owner),
+            expires: None,
+            state: state.into(),
+        }
+    }
+
+    /// Whether anyone can turn the pages, rather than just the owner
+    pub fn shared(mut self, shared: bool) -> Self {
+        if shared {
+            self.owner = None;
+        }
+        self
+    }
+
+    /// How long the buttons keep working for. By default they work forever
+    pub fn lifetime(mut self, lifetime: Duration) -> Self {
+        self.expires = Some(unix_time() + lifetime.as_secs());
+        self
+    }
+
+    /// How many bytes of state can be kept in the buttons without their custom ids getting too
+    /// long, whichever page they're on
+    pub fn state_budget(&self) -> usize {
+        let longest = Self {
+            state: String::new(),
+            ..self.clone()
+        };
+        let longest = longest.id(LONGEST_ACTION, usize::MAX).to_string();
+        MAX_CUSTOM_ID_LENGTH.saturating_sub(longest.len())
+    }
+
+    fn id(&self, action: &str, page: usize) -> CustomId {
+        let owner = self.owner.map_or(0, |owner| owner.get());
+        let expires = self.expires.unwrap_or(0);
+        let payload = format!("{owner}:{expires}:{page}:{}", self.state);
+        CustomId::new(&self.namespace, action, payload)
+    }
+
+    /// Reads the paginator, and the page a button goes to, back out of a custom id
+    pub fn parse(id: &CustomId) -> Option<(Self, usize)> {
+        let mut parts = id.payload.splitn(4, ':');
+        let owner = parts.next()?.parse::<u64>().ok()?;
+        let expires = parts.next()?.parse::<u64>().ok()?;
+        let page = parts.next()?.parse::<usize>().ok()?;
+        let state = parts.next()?;
+
+        let paginator = Self {
+            namespace: id.namespace.clone(),
+            owner: (owner != 0).then(|| UserId::new(owner)),
+            expires: (expires != 0).then_some(expires),
+            state: state.to_owned(),
+        };
+
+        Some((paginator, page))
+    }
+
+    fn has_expired(&self) -> bool {
+        self.expires.is_some_and(|expires| unix_time() >= expires)
+    }
+
+    /// A page, with the buttons for getting to the others
+    pub fn reply(&self, page: usize, content: Page) -> Reply {
+        let total = content.total.max(1);
+        let first_page = page == 0;
+        let last_page = page + 1 >= total;
+        let button = |action: &str, to: usize| CreateButton::new(self.id(action, to));
+
+        let mut reply = Reply::new()
+            .embed(content.embed)
+            .button(button("first", 0).label("⏮").disabled(first_page))
+            .button(
+                button("back", page.saturating_sub(1))
+                    .label("←")
+                    .disabled(first_page),
+            )
+            .button(
+                button("jump", page)
+                    .label(format!("{}/{total}", page + 1))
+                    .style(ButtonStyle::Secondary)
+                    .disabled(total == 1),
+            )
+            .button(button("forward", page + 1).label("→").disabled(last_page))
+            .button(button("last", total - 1).label("⏭").disabled(last_page))
+            .button(button("close", page).label("✓").style(ButtonStyle::Success));
+
+        if let Some((placeholder, options)) = content.menu {
+            let kind = CreateSelectMenuKind::String { options };
+            let menu = CreateSelectMenu::new(self.id("state", 0), kind).placeholder(placeholder);
+            reply = reply.select_menu(menu);
+        }
+
+        reply
+    }
+
+    fn jump_modal(&self, page: usize, total: usize) -> CreateInteractionResponse {
+        let input = CreateInputText::new(InputTextStyle::Short, "Page", PAGE_INPUT)
+            .placeholder(format!("1 to {total}"))
+            .required(true);
+
+        let modal = CreateModal::new(self.id("jump", page), "Go to page")
+            .components(vec![CreateActionRow::InputText(input)]);
+
+        CreateInteractionResponse::Modal(modal)
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn ephemeral(content: impl Into<String>) -> CreateInteractionResponse {
+    let message = CreateInteractionResponseMessage::new()
+        .content(content)
+        .ephemeral(true);
+    CreateInteractionResponse::Message(message)
+}
+
+/// The page number typed into the jump to page modal
+fn modal_input(event: &ComponentEvent) -> Option<String> {
+    let ComponentEvent::Modal(modal) = event else {
+        return None;
+    };
+
+    modal
+        .data
+        .components
+        .iter()
+        .flat_map(|row| &row.components)
+        .find_map(|component| match component {
+            ActionRowComponent::InputText(text) if text.custom_id == PAGE_INPUT => {
+                text.value.clone()
+            }
+            _ => None,
+        })
+}
+
+/// Handles one of a paginator's buttons being pressed, by rendering the page it goes to (given the
+/// paginator's state and a page number) and swapping it into the message.
+pub async fn turn_page<F, R>(
+    discord: &Discord,
+    event: &ComponentEvent,
+    id: &CustomId,
+    render: F,
+) -> CommandResult
+where
+    F: Fn(String, usize) -> R,
+    R: Future<Output = Result<Page, CommandError>>,
+{
+    let (mut paginator, mut page) = Paginator::parse(id).ok_or(CommandError::Expired)?;
+
+    if paginator.has_expired() {
+        return Err(CommandError::Expired);
+    }
+
+    if paginator
+        .owner
+        .is_some_and(|owner| owner != event.user().id)
+    {
+        let response = ephemeral("Sorry! Only the person who asked can turn the pages.");
+        event.respond(discord, response).await?;
+        return Ok(());
+    }
+
+    match (id.action.as_str(), event) {
+        ("first" | "back" | "forward" | "last" | "close", _) => {}
+
+        ("state", ComponentEvent::Component(interaction)) => {
+            let ComponentInteractionDataKind::StringSelect { values } = &interaction.data.kind
+            else {
+                return Err(CommandError::Expired);
+            };
+
+            paginator.state = values.first().cloned().unwrap_or_default();
+            page = 0;
+        }
+
+        // Pressing the page number asks which page to go to
+        ("jump", ComponentEvent::Component(_)) => {
+            let content = render(paginator.state.clone(), page).await?;
+            let modal = paginator.jump_modal(page, content.total);
+            event.respond(discord, modal).await?;
+            return Ok(());
+        }
+
+        ("jump", ComponentEvent::Modal(_)) => {
+            let input = modal_input(event).unwrap_or_default();
+            let content = render(paginator.state.clone(), page).await?;
+
+            match input.trim().parse::<usize>() {
+                Ok(n) if (1..=content.total).contains(&n) => page = n - 1,
+                _ => {
+                    let response = ephemeral(format!(
+                        "Sorry! There's no page \"{input}\", only pages 1 to {}.",
+                        content.total
+                    ));
+                    event.respond(discord, response).await?;
+                    return Ok(());
+                }
+            }
+        }
+
+        (action, _) => {
+            warn!("invalid paginator action \"{action}\"! did you forget to handle it?");
+            return Err(CommandError::Expired);
+        }
+    }
+
+    let mut content = render(paginator.state.clone(), page).await?;
+
+    // The list might have gotten shorter since the buttons were made
+    if page >= content.total && content.total > 0 {
+        page = content.total - 1;
+        content = render(paginator.state.clone(), page).await?;
+    }
+
+    let reply = if id.action == "close" {
+        Reply::new().embed(content.embed)
+    } else {
+        info!("turning to page {}", page + 1);
+        paginator.reply(page, content)
+    };
+
+    let response = CreateInteractionResponse::UpdateMessage(reply.into_message());
+    event.respond(discord, response).await?;
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+use serenity::{all::CommandInteraction, builder::CreateCommand};
+
+use crate::discord::Discord;
+use crate::event_handler::Handler;
+
+use super::cooldown::{Bucket, Cooldown};
+use super::error::{CommandError, CommandResult};
+use super::help::{Category, HelpDetails};
+use super::responder::{Reply, Responder};
+use super::{create_command_fn, CommandDetails};
+
+/// Reloads the asset files. Only the bot's owners can use this.
+pub async fn run(
+    discord: Discord,
+    cmd: &CommandInteraction,
+    handler: Handler,
+    responder: Responder,
+) -> CommandResult {
+    if !handler.config.owners.contains(&cmd.user.id) {
+        return Err(CommandError::NotAllowed);
+    }
+
+    let message = match handler.reload(&*discord).await {
+        Ok(summary) => format!("All done! I reloaded my assets.\n{}", summary.join("\n")),
+        Err(e) => format!("I couldn't reload my assets, so I kept the old ones:\n```\n{e}\n```"),
+    };
+
+    responder
+        .respond(Reply::new().content(message).ephemeral(true))
+        .await?;
+
+    Ok(())
+}
+
+pub fn register() -> CommandDetails {
+    let registration =
+        CreateCommand::new("reload").description("Reload Nano's assets (owners only)");
+    let help = HelpDetails {
+        name: "reload".to_string(),
+        details: "Reload Nano's assets. Only Nano's owners can use this.".to_string(),
+        category: Category::Admin,
+        ..Default::default()
+    };
+
+    let command = create_command_fn(|discord, handler, cmd, responder| async move {
+        run(discord, &cmd, handler, responder).await
+    });
+
+    CommandDetails {
+        name: "reload".to_owned(),
+        registration,
+        help,
+        command,
+        autocomplete: None,
+        // Reloading replaces the assets for everyone, so once at a time is plenty
+        cooldowns: vec![Cooldown::new(Bucket::Global, Duration::from_secs(10))],
+    }
+}
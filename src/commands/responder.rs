@@ -0,0 +1,291 @@
+//! Responding to command interactions.
+//!
+//! Discord only gives us three seconds to respond to an interaction. Commands that do network
+//! work (fetching gifs, looking up users) can take longer than that when the network is slow, so
+//! the dispatcher asks the [Responder] to send a deferred acknowledgement ("Nano is thinking...")
+//! if a command hasn't responded within a latency budget. Commands don't need to care about this:
+//! they respond through the same [Responder], which decides whether the deferral or the response
+//! goes first and picks the right way to send the message.
+use std::sync::Arc;
+use std::time::Duration;
+
+use serenity::all::{
+    CommandInteraction, CreateActionRow, CreateButton, CreateEmbed, CreateInteractionResponse,
+    CreateInteractionResponseFollowup, CreateInteractionResponseMessage, CreateSelectMenu,
+    EditInteractionResponse, InteractionId,
+};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::components::ComponentEvent;
+use crate::discord::Discord;
+
+/// A message to send in response to a command
+#[derive(Debug, Clone, Default)]
+pub struct Reply {
+    content: Option<String>,
+    embeds: Vec<CreateEmbed>,
+    components: Vec<CreateActionRow>,
+    ephemeral: bool,
+}
+
+impl Reply {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn content(mut self, content: impl Into<String>) -> Self {
+        self.content = Some(content.into());
+        self
+    }
+
+    pub fn embed(mut self, embed: CreateEmbed) -> Self {
+        self.embeds.push(embed);
+        self
+    }
+
+    /// Adds a button to the last row of buttons, starting a new row if there isn't one
+    pub fn button(mut self, button: CreateButton) -> Self {
+        match self.components.last_mut() {
+            Some(CreateActionRow::Buttons(buttons)) if buttons.len() < 5 => buttons.push(button),
+            _ => self.components.push(CreateActionRow::Buttons(vec![button])),
+        }
+        self
+    }
+
+    /// Adds a select menu, on a row of its own
+    pub fn select_menu(mut self, menu: CreateSelectMenu) -> Self {
+        self.components.push(CreateActionRow::SelectMenu(menu));
+        self
+    }
+
+    /// Whether only the user who sent the command can see the message
+    pub fn ephemeral(mut self, ephemeral: bool) -> Self {
+        self.ephemeral = ephemeral;
+        self
+    }
+
+    pub fn is_ephemeral(&self) -> bool {
+        self.ephemeral
+    }
+
+    pub fn into_message(self) -> CreateInteractionResponseMessage {
+        let mut message = CreateInteractionResponseMessage::new()
+            .embeds(self.embeds)
+            .components(self.components)
+            .ephemeral(self.ephemeral);
+
+        if let Some(content) = self.content {
+            message = message.content(content);
+        }
+
+        message
+    }
+
+    /// Turns the reply into an edit of an existing message. Unlike the other conversions, this
+    /// always sets the components, so that an edit with no components removes them.
+    pub fn into_edit(self) -> EditInteractionResponse {
+        let mut edit = EditInteractionResponse::new()
+            .embeds(self.embeds)
+            .components(self.components);
+
+        if let Some(content) = self.content {
+            edit = edit.content(content);
+        }
+
+        edit
+    }
+
+    pub fn into_followup(self) -> CreateInteractionResponseFollowup {
+        let mut followup = CreateInteractionResponseFollowup::new()
+            .embeds(self.embeds)
+            .components(self.components)
+            .ephemeral(self.ephemeral);
+
+        if let Some(content) = self.content {
+            followup = followup.content(content);
+        }
+
+        followup
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResponseState {
+    /// Nothing has been sent yet
+    Pending,
+    /// A deferred acknowledgement has been sent, and the real response is still to come
+    Deferred,
+    /// The response has been sent
+    Responded,
+}
+
+/// Sends the response to a command interaction, whether or not it has been deferred.
+///
+/// This is cheap to clone, and every clone refers to the same interaction.
+#[derive(Clone)]
+pub struct Responder {
+    discord: Discord,
+    interaction_id: InteractionId,
+    token: String,
+    // This is held while sending, so that a deferral can't sneak in while the response is on its
+    // way (or vice versa)
+    state: Arc<Mutex<ResponseState>>,
+}
+
+impl Responder {
+    pub fn new(discord: Discord, cmd: &CommandInteraction) -> Self {
+        Self::with_ids(discord, cmd.id, cmd.token.clone())
+    }
+
+    /// A responder for a button, select menu or modal. Responding sends a new message, rather than
+    /// changing the one the component is on.
+    pub fn for_component(discord: Discord, event: &ComponentEvent) -> Self {
+        Self::with_ids(discord, event.id(), event.token().to_owned())
+    }
+
+    fn with_ids(discord: Discord, interaction_id: InteractionId, token: String) -> Self {
+        Self {
+            discord,
+            interaction_id,
+            token,
+            state: Arc::new(Mutex::new(ResponseState::Pending)),
+        }
+    }
+
+    /// Sends a deferred acknowledgement if nothing has been sent within `budget`.
+    ///
+    /// Whichever of the deferral and the response gets the state first decides how the other is
+    /// sent, and once there's been a response the deferral does nothing. The returned [Deferral]
+    /// should be finished once the command is done.
+    pub fn defer_after(&self, budget: Duration) -> Deferral {
+        let responder = self.clone();
+
+        let task = tokio::spawn(async move {
+            tokio::time::sleep(budget).await;
+            if let Err(e) = responder.defer().await {
+                error!("couldn't defer command response: {e}");
+            }
+        });
+
+        Deferral {
+            responder: self.clone(),
+            task,
+        }
+    }
+
+    /// Sends a deferred acknowledgement, if nothing has been sent yet.
+    async fn defer(&self) -> serenity::Result<()> {
+        let mut state = self.state.lock().await;
+
+        if *state == ResponseState::Pending {
+            info!("command is taking a while, deferring response");
+            let response =
+                CreateInteractionResponse::Defer(CreateInteractionResponseMessage::new());
+            self.discord
+                .create_interaction_response(self.interaction_id, &self.token, &response)
+                .await?;
+
+            *state = ResponseState::Deferred;
+        }
+
+        Ok(())
+    }
+
+    /// Responds to a component by changing the message it's on. Anything sent after this is a
+    /// followup.
+    pub async fn update(&self, message: CreateInteractionResponseMessage) -> serenity::Result<()> {
+        let mut state = self.state.lock().await;
+
+        let response = CreateInteractionResponse::UpdateMessage(message);
+        self.discord
+            .create_interaction_response(self.interaction_id, &self.token, &response)
+            .await?;
+
+        *state = ResponseState::Responded;
+        Ok(())
+    }
+
+    /// Sends a reply to the command.
+    ///
+    /// If the command was deferred, this fills in the deferred message. If a response has already
+    /// been sent, this edits it.
+    pub async fn respond(&self, reply: Reply) -> serenity::Result<()> {
+        let mut state = self.state.lock().await;
+
+        match *state {
+            ResponseState::Pending => {
+                let response = CreateInteractionResponse::Message(reply.into_message());
+                self.discord
+                    .create_interaction_response(self.interaction_id, &self.token, &response)
+                    .await?;
+            }
+
+            // A deferred message can't be made ephemeral after the fact, so swap it out for a
+            // followup that is
+            ResponseState::Deferred if reply.is_ephemeral() => {
+                self.discord
+                    .delete_original_interaction_response(&self.token)
+                    .await?;
+                self.discord
+                    .create_followup_message(&self.token, &reply.into_followup())
+                    .await?;
+            }
+
+            ResponseState::Deferred | ResponseState::Responded => {
+                self.discord
+                    .edit_original_interaction_response(&self.token, &reply.into_edit())
+                    .await?;
+            }
+        }
+
+        *state = ResponseState::Responded;
+        Ok(())
+    }
+
+    /// Whether a response (not just a deferral) has been sent
+    pub async fn has_responded(&self) -> bool {
+        *self.state.lock().await == ResponseState::Responded
+    }
+
+    /// Sends an extra message after the response
+    pub async fn followup(&self, reply: Reply) -> serenity::Result<()> {
+        self.discord
+            .create_followup_message(&self.token, &reply.into_followup())
+            .await
+    }
+}
+
+/// A deferral waiting to be sent, from [Responder::defer_after]
+#[must_use = "the deferral should be finished once the command is done"]
+pub struct Deferral {
+    responder: Responder,
+    task: JoinHandle<()>,
+}
+
+impl Deferral {
+    /// Stops the deferral from being sent, now that the command is done. If it's already been
+    /// sent and the command never filled it in, the "Nano is thinking..." message is removed so
+    /// it doesn't hang around forever.
+    pub async fn finish(self) {
+        // With the state held, the task is either yet to start sending or has finished, so it
+        // can't be cut off halfway through
+        let mut state = self.responder.state.lock().await;
+        self.task.abort();
+
+        if *state == ResponseState::Deferred {
+            warn!("command finished without responding, removing the deferred response");
+            if let Err(e) = self
+                .responder
+                .discord
+                .delete_original_interaction_response(&self.responder.token)
+                .await
+            {
+                error!("couldn't remove the deferred response: {e}");
+            }
+
+            *state = ResponseState::Responded;
+        }
+    }
+}
@@ -1,32 +1,51 @@
+use std::time::Duration;
+
 use crate::utils::{get_luna_icon, get_name};
 use rand::{seq::SliceRandom, thread_rng};
 use serde::Deserialize;
 use serenity::{
     all::{CommandInteraction, CreateCommand, CreateEmbedFooter},
-    builder::{CreateEmbed, CreateInteractionResponse, CreateInteractionResponseMessage},
-    prelude::Context,
+    builder::CreateEmbed,
     utils::MessageBuilder,
 };
-use tracing::error;
 
-use super::{create_command_fn, help::HelpDetails, CommandDetails};
+use crate::discord::Discord;
+use crate::event_handler::Handler;
+
+use super::cooldown::{Bucket, Cooldown};
+use super::error::CommandResult;
+use super::help::{Category, HelpDetails};
+use super::responder::{Reply, Responder};
+use super::{create_command_fn, CommandDetails};
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct SayHiData {
-    message: String,
-    gif: String,
+    pub message: String,
+    pub gif: String,
 }
 
-pub async fn run(ctx: Context, cmd: &CommandInteraction, data: &[SayHiData]) {
-    let name = get_name(&ctx, &cmd.user, cmd.guild_id.as_ref()).await;
+pub async fn run(
+    discord: Discord,
+    cmd: &CommandInteraction,
+    handler: Handler,
+    responder: Responder,
+) -> CommandResult {
+    let name = get_name(&*discord, &cmd.user, cmd.guild_id.as_ref()).await;
     let sanitised_name = MessageBuilder::new().push_safe(name).build();
 
-    let message = data.choose(&mut thread_rng()).unwrap();
+    // say_hi.yaml is checked to not be empty when it's loaded
+    let message = handler
+        .say_hi_data
+        .read()
+        .await
+        .choose(&mut thread_rng())
+        .cloned()
+        .unwrap();
     let title = &message.message;
     let gif = &message.gif;
 
     let mut footer = CreateEmbedFooter::new("made by villuna");
-    if let Some(url) = get_luna_icon(&ctx).await {
+    if let Some(url) = get_luna_icon(&*discord).await {
         footer = footer.icon_url(url);
     }
 
@@ -35,12 +54,10 @@ pub async fn run(ctx: Context, cmd: &CommandInteraction, data: &[SayHiData]) {
         .image(gif)
         .footer(footer);
 
-    let message = CreateInteractionResponseMessage::new().embed(embed);
-    let builder = CreateInteractionResponse::Message(message);
+    let reply = Reply::new().embed(embed);
 
-    if let Err(e) = cmd.create_response(&ctx.http, builder).await {
-        error!("error sending response to sayhi command: {e}");
-    }
+    responder.respond(reply).await?;
+    Ok(())
 }
 
 pub fn register() -> CommandDetails {
@@ -48,18 +65,21 @@ pub fn register() -> CommandDetails {
     let help = HelpDetails {
         name: "sayhi".to_string(),
         details: "Say hi to Nano".to_string(),
+        category: Category::Fun,
         ..Default::default()
     };
 
-    let command =
-        create_command_fn(
-            |ctx, handler, cmd| async move { run(ctx, &cmd, &handler.say_hi_data).await },
-        );
+    let command = create_command_fn(|discord, handler, cmd, responder| async move {
+        run(discord, &cmd, handler, responder).await
+    });
 
     CommandDetails {
         name: "sayhi".to_owned(),
         registration,
         help,
         command,
+        autocomplete: None,
+        // Saying hi posts a big gif, so a channel only gets a few at a time
+        cooldowns: vec![Cooldown::new(Bucket::Channel, Duration::from_secs(30)).uses(3)],
     }
 }
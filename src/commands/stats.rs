@@ -0,0 +1,415 @@
+//! `/stats`, for seeing who's been hugging who.
+//!
+//! Every use of an action is recorded in [storage](crate::storage), and these are just different
+//! ways of counting them up. Results are paginated like /help, with a menu for changing how far
+//! back to count.
+use std::time::{Duration, SystemTime};
+
+use serenity::all::{
+    AutocompleteChoice, CommandInteraction, CreateCommandOption, CreateEmbed, CreateEmbedFooter,
+    CreateSelectMenuOption, GuildId, Mentionable, User, UserId,
+};
+
+use crate::components::{create_component_fn, ComponentFn};
+use crate::event_handler::Handler;
+
+use super::cooldown::{Bucket, Cooldown};
+use super::error::{CommandError, CommandResult};
+use super::help::{suggest_commands, Category};
+use super::options::{command_options, CommandOptions};
+use super::paginator::{turn_page, Page, Paginator};
+use super::responder::Responder;
+use super::subcommands::{leaf_options, CommandTree, SubCommand};
+use super::{create_autocomplete_fn, create_command_fn, CommandDetails};
+
+const PAGE_LENGTH: usize = 10;
+
+/// Counting everything up is more work than most commands, so each server only gets a few goes
+/// at a time
+fn cooldown() -> Cooldown {
+    Cooldown::new(Bucket::Guild, Duration::from_secs(60)).uses(10)
+}
+
+/// How long the buttons keep working. Anyone can use them, so old stats messages don't stay
+/// open forever
+const LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The namespace of the stats message's buttons
+pub const NAMESPACE: &str = "stats";
+
+/// How far back to count
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Window {
+    Week,
+    Month,
+    AllTime,
+}
+
+impl Window {
+    const ALL: [Window; 3] = [Window::Week, Window::Month, Window::AllTime];
+
+    /// The name used in options and custom ids
+    fn name(self) -> &'static str {
+        match self {
+            Window::Week => "week",
+            Window::Month => "month",
+            Window::AllTime => "all",
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            Window::Week => "This week",
+            Window::Month => "This month",
+            Window::AllTime => "All time",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|window| window.name() == name)
+    }
+
+    /// When the window starts, if it does
+    fn since(self) -> Option<SystemTime> {
+        let days = match self {
+            Window::Week => 7,
+            Window::Month => 30,
+            Window::AllTime => return None,
+        };
+
+        SystemTime::now().checked_sub(Duration::from_secs(days * 24 * 60 * 60))
+    }
+}
+
+/// What's being counted
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Query {
+    /// The actions a user has done the most
+    Given(UserId),
+    /// The actions that have been done to a user the most
+    Received(UserId),
+    /// Who's done an action the most in a guild
+    Top(GuildId, String),
+    /// The actions everyone's done the most
+    Popular,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Stats {
+    query: Query,
+    window: Window,
+}
+
+impl Stats {
+    /// The stats, to be kept in the message's buttons, like `week:given:1234`. The action's name
+    /// goes last, so it doesn't matter what's in it.
+    fn to_state(&self) -> String {
+        let query = match &self.query {
+            Query::Given(user) => format!("given:{user}"),
+            Query::Received(user) => format!("received:{user}"),
+            Query::Top(guild, kind) => format!("top:{guild}:{kind}"),
+            Query::Popular => "popular".to_owned(),
+        };
+
+        format!("{}:{query}", self.window.name())
+    }
+
+    fn from_state(state: &str) -> Option<Self> {
+        let (window, query) = state.split_once(':')?;
+        let window = Window::from_name(window)?;
+
+        let id = |part: Option<&str>| part?.parse::<u64>().ok().filter(|id| *id != 0);
+        let mut parts = query.splitn(3, ':');
+        let query = match (parts.next()?, parts.next(), parts.next()) {
+            ("given", user, None) => Query::Given(UserId::new(id(user)?)),
+            ("received", user, None) => Query::Received(UserId::new(id(user)?)),
+            ("top", guild, Some(kind)) => Query::Top(GuildId::new(id(guild)?), kind.to_owned()),
+            ("popular", None, None) => Query::Popular,
+            _ => return None,
+        };
+
+        Some(Self { query, window })
+    }
+
+    /// The same stats, counted over a different window
+    fn with_window(&self, window: Window) -> Self {
+        Self {
+            query: self.query.clone(),
+            window,
+        }
+    }
+}
+
+/// Counts up the stats, returning each row's label and count, most first
+async fn rows(handler: &Handler, stats: &Stats) -> Result<Vec<(String, u64)>, CommandError> {
+    let since = stats.window.since();
+    let actions = |rows: Vec<(String, u64)>| {
+        rows.into_iter()
+            .map(|(kind, uses)| (format!("/{kind}"), uses))
+            .collect()
+    };
+
+    Ok(match &stats.query {
+        Query::Given(user) => actions(handler.storage.actions_given(*user, since).await?),
+        Query::Received(user) => actions(handler.storage.actions_received(*user, since).await?),
+        Query::Popular => actions(handler.storage.popular_actions(since).await?),
+        Query::Top(guild, kind) => handler
+            .storage
+            .top_users(*guild, kind.clone(), since)
+            .await?
+            .into_iter()
+            .map(|(user, uses)| (user.mention().to_string(), uses))
+            .collect(),
+    })
+}
+
+/// The menu for counting over a different window
+fn window_menu(stats: &Stats) -> Vec<CreateSelectMenuOption> {
+    Window::ALL
+        .into_iter()
+        .map(|window| {
+            CreateSelectMenuOption::new(window.title(), stats.with_window(window).to_state())
+                .default_selection(window == stats.window)
+        })
+        .collect()
+}
+
+/// Renders a page of stats from scratch, so any instance of nano can do it at any time
+async fn render_page(handler: &Handler, stats: &Stats, page: usize) -> Result<Page, CommandError> {
+    let rows = rows(handler, stats).await?;
+    let page_count = rows.len().div_ceil(PAGE_LENGTH).max(1);
+
+    let (title, heading) = match &stats.query {
+        Query::Given(user) => ("Most given actions".to_owned(), Some(user)),
+        Query::Received(user) => ("Most received actions".to_owned(), Some(user)),
+        Query::Top(_, kind) => (format!("Top /{kind} users"), None),
+        Query::Popular => ("Most popular actions".to_owned(), None),
+    };
+
+    let mut lines = heading
+        .map(|user| format!("For {}, in every server\n", user.mention()))
+        .into_iter()
+        .collect::<Vec<_>>();
+
+    if rows.is_empty() {
+        lines.push("Nothing yet!".to_owned());
+    }
+
+    let start = page * PAGE_LENGTH;
+    for (i, (label, uses)) in rows.iter().enumerate().skip(start).take(PAGE_LENGTH) {
+        lines.push(format!("**{}.** {label} × {uses}", i + 1));
+    }
+
+    let embed = CreateEmbed::new()
+        .title(title)
+        .description(lines.join("\n"))
+        .footer(CreateEmbedFooter::new(format!(
+            "{} · Page {}/{page_count}",
+            stats.window.title(),
+            page + 1
+        )));
+
+    Ok(Page::new(embed, page_count).menu("Change the time window", window_menu(stats)))
+}
+
+/// Lets every subcommand's `window` option offer the same choices
+trait WindowChoices {
+    fn window_choices(self) -> Self;
+}
+
+impl WindowChoices for CreateCommandOption {
+    fn window_choices(self) -> Self {
+        Window::ALL.into_iter().fold(self, |option, window| {
+            option.add_string_choice(window.title(), window.name())
+        })
+    }
+}
+
+command_options! {
+    struct UserStatsOptions {
+        /// whose stats to show (yours, if no one's)
+        user: Option<User>,
+        /// how far back to count (all time, if not given)
+        window: Option<String> => window_choices(),
+    }
+}
+
+command_options! {
+    struct TopOptions {
+        /// the action to count
+        action: String => set_autocomplete(true),
+        /// how far back to count (all time, if not given)
+        window: Option<String> => window_choices(),
+    }
+}
+
+command_options! {
+    struct PopularOptions {
+        /// how far back to count (all time, if not given)
+        window: Option<String> => window_choices(),
+    }
+}
+
+fn window(name: Option<String>) -> Result<Window, CommandError> {
+    match name {
+        None => Ok(Window::AllTime),
+        Some(name) => Window::from_name(&name)
+            .ok_or_else(|| CommandError::BadOption(format!("I can't count over \"{name}\"!"))),
+    }
+}
+
+/// Sends the first page of some stats
+async fn show(
+    stats: Stats,
+    handler: Handler,
+    cmd: CommandInteraction,
+    responder: Responder,
+) -> CommandResult {
+    let page = render_page(&handler, &stats, 0).await?;
+
+    // Stats aren't secret, so anyone can look through them
+    let paginator = Paginator::new(NAMESPACE, cmd.user.id, stats.to_state())
+        .shared(true)
+        .lifetime(LIFETIME);
+    responder.respond(paginator.reply(0, page)).await?;
+
+    Ok(())
+}
+
+/// Shows the actions a user has given or received the most
+async fn user_stats(
+    given: bool,
+    handler: Handler,
+    cmd: CommandInteraction,
+    responder: Responder,
+) -> CommandResult {
+    let options = UserStatsOptions::parse(&leaf_options(&cmd))?;
+    let user = options.user.map_or(cmd.user.id, |user| user.id);
+
+    let stats = Stats {
+        query: if given {
+            Query::Given(user)
+        } else {
+            Query::Received(user)
+        },
+        window: window(options.window)?,
+    };
+
+    show(stats, handler, cmd, responder).await
+}
+
+/// Shows who's done an action the most in this server
+async fn top(handler: Handler, cmd: CommandInteraction, responder: Responder) -> CommandResult {
+    let options = TopOptions::parse(&leaf_options(&cmd))?;
+    let guild = cmd
+        .guild_id
+        .ok_or_else(|| CommandError::BadOption("That only works in a server!".to_owned()))?;
+
+    let kind = options.action.trim().trim_start_matches('/').to_owned();
+    if !handler
+        .actions
+        .read()
+        .await
+        .iter()
+        .any(|action| action.kind == kind)
+    {
+        return Err(CommandError::BadOption(format!(
+            "I don't have an action called \"{kind}\"!"
+        )));
+    }
+
+    let stats = Stats {
+        query: Query::Top(guild, kind),
+        window: window(options.window)?,
+    };
+
+    show(stats, handler, cmd, responder).await
+}
+
+/// Shows the actions everyone's done the most
+async fn popular(handler: Handler, cmd: CommandInteraction, responder: Responder) -> CommandResult {
+    let options = PopularOptions::parse(&leaf_options(&cmd))?;
+    let stats = Stats {
+        query: Query::Popular,
+        window: window(options.window)?,
+    };
+
+    show(stats, handler, cmd, responder).await
+}
+
+/// Turns the pages of stats messages
+pub fn components() -> ComponentFn {
+    create_component_fn(|discord, handler, event, id| async move {
+        let render = |state: String, page| {
+            let handler = &handler;
+            async move {
+                let stats = Stats::from_state(&state).ok_or(CommandError::Expired)?;
+                render_page(handler, &stats, page).await
+            }
+        };
+
+        turn_page(&discord, &event, &id, render).await
+    })
+}
+
+/// Suggests actions for the `action` option
+async fn autocomplete(handler: Handler, cmd: &CommandInteraction) -> Vec<AutocompleteChoice> {
+    let Some(focused) = cmd.data.autocomplete() else {
+        return Vec::new();
+    };
+
+    if focused.name != "action" {
+        return Vec::new();
+    }
+
+    let actions = handler.actions.read().await;
+    let data = handler.help_data.read().await;
+    suggest_commands(&data, focused.value, |details| {
+        actions.iter().any(|action| action.kind == details.name)
+    })
+}
+
+pub fn register() -> CommandDetails {
+    let given = create_command_fn(|_discord, handler, cmd, responder| {
+        user_stats(true, handler, cmd, responder)
+    });
+    let received = create_command_fn(|_discord, handler, cmd, responder| {
+        user_stats(false, handler, cmd, responder)
+    });
+    let top = create_command_fn(|_discord, handler, cmd, responder| top(handler, cmd, responder));
+    let popular =
+        create_command_fn(|_discord, handler, cmd, responder| popular(handler, cmd, responder));
+    let autocomplete = create_autocomplete_fn(|_discord, handler, cmd| async move {
+        autocomplete(handler, &cmd).await
+    });
+
+    CommandTree::new("stats", "See who's been hugging who")
+        .category(Category::Fun)
+        .cooldown(cooldown())
+        .subcommand(
+            SubCommand::new(
+                "given",
+                "See the actions someone does the most, in every server",
+                given,
+            )
+            .options(UserStatsOptions::options()),
+        )
+        .subcommand(
+            SubCommand::new(
+                "received",
+                "See the actions done to someone the most, in every server",
+                received,
+            )
+            .options(UserStatsOptions::options()),
+        )
+        .subcommand(
+            SubCommand::new("top", "See who does an action the most in this server", top)
+                .options(TopOptions::options())
+                .autocomplete(autocomplete),
+        )
+        .subcommand(
+            SubCommand::new("popular", "See the actions everyone does the most", popular)
+                .options(PopularOptions::options()),
+        )
+        .build()
+}
@@ -0,0 +1,302 @@
+//! Commands made of subcommands, like `/config commands disable`.
+//!
+//! A [CommandTree] declares the whole command in one place: its subcommands (optionally sorted
+//! into groups), their options and the function that runs each one. From that it builds the
+//! registration discord needs, the help pages, and a [CommandFn] which works out which
+//! subcommand was used and runs it.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serenity::all::{
+    CommandDataOption, CommandDataOptionValue, CommandInteraction, CommandOptionType,
+    CreateCommand, CreateCommandOption, InteractionContext, Permissions, ResolvedOption,
+    ResolvedValue,
+};
+
+use super::cooldown::Cooldown;
+use super::error::CommandError;
+use super::help::{Category, HelpDetails};
+use super::{create_autocomplete_fn, create_command_fn, AutocompleteFn, CommandDetails, CommandFn};
+
+/// A command whose options are subcommands and groups of subcommands
+pub struct CommandTree {
+    name: String,
+    description: String,
+    category: Category,
+    cooldowns: Vec<Cooldown>,
+    permissions: Option<Permissions>,
+    guild_only: bool,
+    children: Vec<Child>,
+}
+
+enum Child {
+    Group(SubCommandGroup),
+    Leaf(SubCommand),
+}
+
+/// A group of subcommands, which is itself used like a subcommand (e.g. the `commands` in
+/// `/config commands disable`)
+pub struct SubCommandGroup {
+    name: String,
+    description: String,
+    subcommands: Vec<SubCommand>,
+}
+
+/// A subcommand that actually does something
+pub struct SubCommand {
+    name: String,
+    description: String,
+    options: Vec<CreateCommandOption>,
+    command: CommandFn,
+    autocomplete: Option<AutocompleteFn>,
+}
+
+impl CommandTree {
+    pub fn new(name: impl Into<String>, description: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            category: Category::default(),
+            cooldowns: Vec::new(),
+            permissions: None,
+            guild_only: false,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn category(mut self, category: Category) -> Self {
+        self.category = category;
+        self
+    }
+
+    /// Limits how often the command can be used. This counts uses of all its subcommands together
+    pub fn cooldown(mut self, cooldown: Cooldown) -> Self {
+        self.cooldowns.push(cooldown);
+        self
+    }
+
+    /// Hides the command from members without these permissions, unless the server's admins
+    /// decide otherwise in their integration settings
+    pub fn permissions(mut self, permissions: Permissions) -> Self {
+        self.permissions = Some(permissions);
+        self
+    }
+
+    /// Only lets the command be used in servers, not DMs
+    pub fn guild_only(mut self) -> Self {
+        self.guild_only = true;
+        self
+    }
+
+    pub fn group(mut self, group: SubCommandGroup) -> Self {
+        self.children.push(Child::Group(group));
+        self
+    }
+
+    pub fn subcommand(mut self, subcommand: SubCommand) -> Self {
+        self.children.push(Child::Leaf(subcommand));
+        self
+    }
+
+    /// Builds the command's registration, help, and the functions that send each interaction to
+    /// the right subcommand
+    pub fn build(self) -> CommandDetails {
+        let mut registration = CreateCommand::new(&self.name).description(&self.description);
+        if let Some(permissions) = self.permissions {
+            registration = registration.default_member_permissions(permissions);
+        }
+        if self.guild_only {
+            registration = registration.contexts(vec![InteractionContext::Guild]);
+        }
+
+        let mut help_children = Vec::new();
+        let mut commands = HashMap::new();
+        let mut autocompletes = HashMap::new();
+
+        for child in self.children {
+            match child {
+                Child::Leaf(leaf) => {
+                    help_children.push(leaf.help(self.category));
+                    registration = registration.add_option(leaf.registration());
+                    let path = leaf.name.clone();
+                    leaf.insert_into(&path, &mut commands, &mut autocompletes);
+                }
+
+                Child::Group(group) => {
+                    let mut option = CreateCommandOption::new(
+                        CommandOptionType::SubCommandGroup,
+                        &group.name,
+                        &group.description,
+                    );
+                    let mut help = HelpDetails {
+                        name: group.name.clone(),
+                        details: group.description.clone(),
+                        category: self.category,
+                        sub_commands: Vec::new(),
+                    };
+
+                    for leaf in group.subcommands {
+                        help.sub_commands.push(leaf.help(self.category));
+                        option = option.add_sub_option(leaf.registration());
+                        let path = format!("{} {}", group.name, leaf.name);
+                        leaf.insert_into(&path, &mut commands, &mut autocompletes);
+                    }
+
+                    help_children.push(help);
+                    registration = registration.add_option(option);
+                }
+            }
+        }
+
+        let help = HelpDetails {
+            name: self.name.clone(),
+            details: self.description,
+            category: self.category,
+            sub_commands: help_children,
+        };
+
+        let commands = Arc::new(commands);
+        let command = create_command_fn(move |discord, handler, cmd, responder| {
+            let commands = Arc::clone(&commands);
+            async move {
+                let path = subcommand_path(&cmd.data.options);
+                let command = commands.get(&path).cloned().ok_or_else(|| {
+                    CommandError::UnknownCommand(format!("{} {path}", cmd.data.name))
+                })?;
+
+                command(discord, handler, cmd, responder).await
+            }
+        });
+
+        let autocomplete = (!autocompletes.is_empty()).then(|| {
+            let autocompletes = Arc::new(autocompletes);
+            create_autocomplete_fn(move |discord, handler, cmd| {
+                let autocompletes = Arc::clone(&autocompletes);
+                async move {
+                    let path = subcommand_path(&cmd.data.options);
+                    match autocompletes.get(&path).cloned() {
+                        Some(autocomplete) => autocomplete(discord, handler, cmd).await,
+                        None => Vec::new(),
+                    }
+                }
+            })
+        });
+
+        CommandDetails {
+            name: self.name,
+            registration,
+            help,
+            command,
+            autocomplete,
+            cooldowns: self.cooldowns,
+        }
+    }
+}
+
+impl SubCommandGroup {
+    pub fn new(name: impl Into<String>, description: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            subcommands: Vec::new(),
+        }
+    }
+
+    pub fn subcommand(mut self, subcommand: SubCommand) -> Self {
+        self.subcommands.push(subcommand);
+        self
+    }
+}
+
+impl SubCommand {
+    /// A subcommand which runs `command`. Its options can be read with [leaf_options]
+    pub fn new(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        command: CommandFn,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            options: Vec::new(),
+            command,
+            autocomplete: None,
+        }
+    }
+
+    /// Adds options, like the ones from a [CommandOptions] struct
+    ///
+    /// [CommandOptions]: super::options::CommandOptions
+    pub fn options(mut self, options: impl IntoIterator<Item = CreateCommandOption>) -> Self {
+        self.options.extend(options);
+        self
+    }
+
+    pub fn autocomplete(mut self, autocomplete: AutocompleteFn) -> Self {
+        self.autocomplete = Some(autocomplete);
+        self
+    }
+
+    fn registration(&self) -> CreateCommandOption {
+        self.options.iter().cloned().fold(
+            CreateCommandOption::new(CommandOptionType::SubCommand, &self.name, &self.description),
+            CreateCommandOption::add_sub_option,
+        )
+    }
+
+    fn help(&self, category: Category) -> HelpDetails {
+        HelpDetails {
+            name: self.name.clone(),
+            details: self.description.clone(),
+            category,
+            sub_commands: Vec::new(),
+        }
+    }
+
+    fn insert_into(
+        self,
+        path: &str,
+        commands: &mut HashMap<String, CommandFn>,
+        autocompletes: &mut HashMap<String, AutocompleteFn>,
+    ) {
+        commands.insert(path.to_owned(), self.command);
+        if let Some(autocomplete) = self.autocomplete {
+            autocompletes.insert(path.to_owned(), autocomplete);
+        }
+    }
+}
+
+/// The names of the subcommand (and group) that were used, separated by spaces
+fn subcommand_path(options: &[CommandDataOption]) -> String {
+    let mut path = Vec::new();
+    let mut options = options;
+
+    while let Some(option) = options.first() {
+        match &option.value {
+            CommandDataOptionValue::SubCommandGroup(inner)
+            | CommandDataOptionValue::SubCommand(inner) => {
+                path.push(option.name.as_str());
+                options = inner;
+            }
+            _ => break,
+        }
+    }
+
+    path.join(" ")
+}
+
+/// The options given to the subcommand that was used, rather than the subcommand itself
+pub fn leaf_options(cmd: &CommandInteraction) -> Vec<ResolvedOption<'_>> {
+    let mut options = cmd.data.options();
+
+    // A subcommand (or group) is always the only option at its level
+    loop {
+        match options.as_mut_slice() {
+            [ResolvedOption {
+                value: ResolvedValue::SubCommandGroup(inner) | ResolvedValue::SubCommand(inner),
+                ..
+            }] => options = std::mem::take(inner),
+            _ => return options,
+        }
+    }
+}
@@ -0,0 +1,214 @@
+//! Routing component interactions (buttons, select menus and modals) to whatever made them.
+//!
+//! Every component's custom id is a [CustomId]: a namespace, an action and a payload, separated by
+//! colons (e.g. `help:page:2`). Handlers are registered per namespace with the
+//! [ComponentRouter], and get every interaction in their namespace. Anything that can't be routed,
+//! or that belongs to something which has since expired, gets a polite message instead of
+//! discord's "This interaction failed".
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use serenity::all::{
+    ComponentInteraction, CreateInteractionResponse, InteractionId, ModalInteraction, User,
+};
+use tracing::{info, warn};
+
+use crate::commands::error::{report_error, CommandError, CommandResult};
+use crate::commands::responder::{Reply, Responder};
+use crate::discord::Discord;
+use crate::event_handler::Handler;
+
+/// Discord doesn't allow custom ids longer than this
+pub const MAX_CUSTOM_ID_LENGTH: usize = 100;
+
+/// The structured custom id of a component
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomId {
+    /// Which handler the component belongs to
+    pub namespace: String,
+    /// What the component does
+    pub action: String,
+    /// Any other data the handler needs. This can contain colons
+    pub payload: String,
+}
+
+impl CustomId {
+    pub fn new(
+        namespace: impl Into<String>,
+        action: impl Into<String>,
+        payload: impl Into<String>,
+    ) -> Self {
+        Self {
+            namespace: namespace.into(),
+            action: action.into(),
+            payload: payload.into(),
+        }
+    }
+}
+
+impl fmt::Display for CustomId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.namespace, self.action, self.payload)
+    }
+}
+
+impl From<CustomId> for String {
+    fn from(id: CustomId) -> Self {
+        let id = id.to_string();
+        // Some of what goes in here comes from users, so this is logged rather than asserted.
+        // Discord will refuse the message, which gets reported like any other error
+        if id.len() > MAX_CUSTOM_ID_LENGTH {
+            warn!("custom id {id} is too long");
+        }
+        id
+    }
+}
+
+impl FromStr for CustomId {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let mut parts = s.splitn(3, ':');
+        let namespace = parts.next().filter(|ns| !ns.is_empty()).ok_or(())?;
+        let action = parts.next().ok_or(())?;
+        let payload = parts.next().unwrap_or_default();
+
+        Ok(Self::new(namespace, action, payload))
+    }
+}
+
+/// A component interaction
+#[derive(Debug, Clone)]
+pub enum ComponentEvent {
+    /// A button was pressed, or something was picked from a select menu
+    Component(Box<ComponentInteraction>),
+    /// A modal was submitted
+    Modal(Box<ModalInteraction>),
+}
+
+impl ComponentEvent {
+    pub fn id(&self) -> InteractionId {
+        match self {
+            ComponentEvent::Component(interaction) => interaction.id,
+            ComponentEvent::Modal(interaction) => interaction.id,
+        }
+    }
+
+    pub fn token(&self) -> &str {
+        match self {
+            ComponentEvent::Component(interaction) => &interaction.token,
+            ComponentEvent::Modal(interaction) => &interaction.token,
+        }
+    }
+
+    pub fn custom_id(&self) -> &str {
+        match self {
+            ComponentEvent::Component(interaction) => &interaction.data.custom_id,
+            ComponentEvent::Modal(interaction) => &interaction.data.custom_id,
+        }
+    }
+
+    /// The user who used the component
+    pub fn user(&self) -> &User {
+        match self {
+            ComponentEvent::Component(interaction) => &interaction.user,
+            ComponentEvent::Modal(interaction) => &interaction.user,
+        }
+    }
+
+    /// Sends the response to the interaction
+    pub async fn respond(
+        &self,
+        discord: &Discord,
+        response: CreateInteractionResponse,
+    ) -> serenity::Result<()> {
+        discord
+            .create_interaction_response(self.id(), self.token(), &response)
+            .await
+    }
+}
+
+/// An async function that handles the components in a namespace. Like a [CommandFn], but for
+/// components.
+///
+/// [CommandFn]: crate::commands::CommandFn
+pub type ComponentFn = Arc<
+    dyn Fn(
+            Discord,
+            Handler,
+            ComponentEvent,
+            CustomId,
+        ) -> Pin<Box<dyn Future<Output = CommandResult> + Send>>
+        + Send
+        + Sync,
+>;
+
+/// Turns an async function into a [ComponentFn]
+pub fn create_component_fn<F, R>(f: F) -> ComponentFn
+where
+    F: Fn(Discord, Handler, ComponentEvent, CustomId) -> R + Send + Sync + 'static,
+    R: Future<Output = CommandResult> + Send + 'static,
+{
+    Arc::new(move |discord, handler, event, id| Box::pin(f(discord, handler, event, id)))
+}
+
+/// Sends component interactions to the handler for their namespace
+#[derive(Default)]
+pub struct ComponentRouter {
+    handlers: HashMap<String, ComponentFn>,
+}
+
+impl ComponentRouter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sends every component in `namespace` to `handler`
+    pub fn register(&mut self, namespace: impl Into<String>, handler: ComponentFn) {
+        let namespace = namespace.into();
+        if self.handlers.insert(namespace.clone(), handler).is_some() {
+            warn!("component namespace \"{namespace}\" was registered twice");
+        }
+    }
+
+    /// Runs the handler for a component interaction, or lets the user know that it can't be used
+    pub async fn route(&self, discord: Discord, handler: Handler, event: ComponentEvent) {
+        let responder = Responder::for_component(Arc::clone(&discord), &event);
+
+        let result = match CustomId::from_str(event.custom_id()) {
+            Ok(id) => match self.handlers.get(&id.namespace).cloned() {
+                Some(component) => component(discord, handler, event, id).await,
+                None => Err(CommandError::Expired),
+            },
+            Err(()) => Err(CommandError::Expired),
+        };
+
+        match result {
+            Ok(()) => {}
+
+            // This isn't really an error, it just means someone clicked on something old
+            Err(e @ CommandError::Expired) => {
+                info!("component can't be handled, sending fallback response");
+                let reply = Reply::new().content(e.user_message()).ephemeral(true);
+                if let Err(e) = responder.respond(reply).await {
+                    warn!("couldn't send fallback response: {e}");
+                }
+            }
+
+            // Components that do the same thing as a command are held to its guild's settings
+            Err(e @ (CommandError::Disabled | CommandError::WrongChannel(_))) => {
+                info!("{e}");
+                let reply = Reply::new().content(e.user_message()).ephemeral(true);
+                if let Err(e) = responder.respond(reply).await {
+                    warn!("couldn't explain why the component wasn't used: {e}");
+                }
+            }
+
+            Err(e) => report_error(&responder, e).await,
+        }
+    }
+}
@@ -0,0 +1,537 @@
+//! Runtime configuration for the bot.
+//!
+//! Configuration is layered. Starting from the defaults, each of these sources overrides the one
+//! before it:
+//!
+//! 1. A config file (YAML, or TOML if the file ends in `.toml`)
+//! 2. `NANO_*` environment variables
+//! 3. Command line flags
+//!
+//! The finished config is validated before the bot connects to discord, so that mistakes are
+//! reported all at once instead of one panic at a time.
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::{Args, ValueEnum};
+use serde::Deserialize;
+use serenity::all::{GatewayIntents, GuildId, UserId};
+
+use std::collections::HashMap;
+
+use crate::gifs::{self, ProviderConfig, RequestPolicy};
+use crate::registration::Target;
+
+/// The config files that are looked for if no path is given explicitly
+const DEFAULT_CONFIG_PATHS: &[&str] = &["nano.yaml", "nano.toml"];
+
+/// Command line flags that override values from the config file.
+#[derive(Args, Debug, Default, Clone)]
+pub struct ConfigArgs {
+    /// Path to the config file (YAML or TOML). Defaults to ./nano.yaml or ./nano.toml
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+
+    /// The bot token
+    #[arg(long)]
+    pub token: Option<String>,
+
+    /// A file to read the bot token from
+    #[arg(long)]
+    pub token_file: Option<PathBuf>,
+
+    /// Path to actions.yaml
+    #[arg(long)]
+    pub actions: Option<PathBuf>,
+
+    /// Path to say_hi.yaml
+    #[arg(long)]
+    pub say_hi: Option<PathBuf>,
+
+    /// Path to triggers.yaml
+    #[arg(long)]
+    pub triggers: Option<PathBuf>,
+
+    /// The directory log files will be written to
+    #[arg(long)]
+    pub log_dir: Option<PathBuf>,
+
+    /// The SQLite database nano saves things in
+    #[arg(long)]
+    pub database: Option<PathBuf>,
+
+    /// Gateway intents, as a comma separated list (e.g. GUILD_MESSAGES,MESSAGE_CONTENT)
+    #[arg(long, value_delimiter = ',')]
+    pub intents: Option<Vec<String>>,
+
+    /// Where to register slash commands
+    #[arg(long, value_enum)]
+    pub registration: Option<RegistrationMode>,
+
+    /// A guild to register commands in. Can be given more than once
+    #[arg(long = "guild")]
+    pub guilds: Vec<GuildId>,
+
+    /// A user who is allowed to use owner-only commands. Can be given more than once
+    #[arg(long = "owner")]
+    pub owners: Vec<UserId>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The bot token. Takes precedence over a `token_file` from the same place
+    pub token: Option<String>,
+    /// A file containing the bot token
+    pub token_file: Option<PathBuf>,
+    pub assets: AssetPaths,
+    pub log_dir: PathBuf,
+    /// The SQLite database where things like each server's settings are saved. If this is null,
+    /// they're kept in memory and forgotten when the bot stops.
+    pub database: Option<PathBuf>,
+    /// The names of the gateway intents to connect with, e.g. "GUILD_MESSAGES"
+    pub intents: Vec<String>,
+    pub registration: RegistrationConfig,
+    pub gifs: GifConfig,
+    pub commands: CommandConfig,
+    pub interactions: InteractionConfig,
+    /// Users who are allowed to use owner-only commands
+    pub owners: Vec<UserId>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AssetPaths {
+    pub actions: PathBuf,
+    pub say_hi: PathBuf,
+    pub triggers: PathBuf,
+}
+
+/// Where slash commands get registered
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RegistrationConfig {
+    pub mode: RegistrationMode,
+    /// Guilds to register commands in, if the mode is `guilds` or `both`. Guild commands update
+    /// instantly, so this is handy for staging changes in a test server.
+    pub guilds: Vec<GuildId>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum RegistrationMode {
+    /// Register commands globally
+    #[default]
+    Global,
+    /// Register commands only in the configured guilds
+    Guilds,
+    /// Register commands globally and in the configured guilds
+    Both,
+}
+
+impl RegistrationConfig {
+    /// Every place commands should be registered
+    pub fn targets(&self) -> Vec<Target> {
+        let global = match self.mode {
+            RegistrationMode::Global | RegistrationMode::Both => Some(Target::Global),
+            RegistrationMode::Guilds => None,
+        };
+
+        let guilds = match self.mode {
+            RegistrationMode::Guilds | RegistrationMode::Both => self.guilds.as_slice(),
+            RegistrationMode::Global => &[],
+        };
+
+        global
+            .into_iter()
+            .chain(guilds.iter().copied().map(Target::Guild))
+            .collect()
+    }
+
+    /// The places commands shouldn't be registered any more, so ones left over from a different
+    /// mode can be removed
+    pub fn unused_targets(&self) -> Vec<Target> {
+        match self.mode {
+            RegistrationMode::Global => self.guilds.iter().copied().map(Target::Guild).collect(),
+            RegistrationMode::Guilds => vec![Target::Global],
+            RegistrationMode::Both => Vec::new(),
+        }
+    }
+}
+
+/// Where action commands get their gifs from
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GifConfig {
+    /// The names of the providers to try, in order. Actions can override this in actions.yaml
+    pub providers: Vec<String>,
+    /// Extra providers, by name
+    pub custom: HashMap<String, ProviderConfig>,
+    /// Timeouts, retries and circuit breaking for every provider
+    pub policy: RequestPolicy,
+}
+
+impl Default for GifConfig {
+    fn default() -> Self {
+        Self {
+            providers: vec![gifs::OTAKUGIFS.to_owned(), gifs::LOCAL.to_owned()],
+            custom: HashMap::new(),
+            policy: RequestPolicy::default(),
+        }
+    }
+}
+
+/// How commands are run
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CommandConfig {
+    /// How long a command can take before a deferred response is sent, in milliseconds. Discord
+    /// gives up on interactions after three seconds, so this should be comfortably less than that.
+    pub defer_after_ms: u64,
+}
+
+impl Default for CommandConfig {
+    fn default() -> Self {
+        Self {
+            defer_after_ms: 1500,
+        }
+    }
+}
+
+impl CommandConfig {
+    pub fn defer_after(&self) -> Duration {
+        Duration::from_millis(self.defer_after_ms)
+    }
+}
+
+/// How nano decides whether she was "just used", for replies like "good bot"
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InteractionConfig {
+    /// How long after doing something nano will still respond, in seconds
+    pub window_secs: u64,
+    /// Only respond to the user nano did something for, rather than anyone in the channel
+    pub per_user: bool,
+}
+
+impl Default for InteractionConfig {
+    fn default() -> Self {
+        Self {
+            window_secs: 60,
+            per_user: false,
+        }
+    }
+}
+
+impl InteractionConfig {
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window_secs)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            token: None,
+            token_file: None,
+            assets: AssetPaths::default(),
+            log_dir: PathBuf::from("./log"),
+            database: Some(PathBuf::from("nano.db")),
+            intents: ["GUILD_MESSAGES", "DIRECT_MESSAGES", "MESSAGE_CONTENT"]
+                .map(String::from)
+                .to_vec(),
+            registration: RegistrationConfig::default(),
+            gifs: GifConfig::default(),
+            commands: CommandConfig::default(),
+            interactions: InteractionConfig::default(),
+            owners: Vec::new(),
+        }
+    }
+}
+
+impl Default for AssetPaths {
+    fn default() -> Self {
+        Self {
+            actions: PathBuf::from("assets/actions.yaml"),
+            say_hi: PathBuf::from("assets/say_hi.yaml"),
+            triggers: PathBuf::from("assets/triggers.yaml"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config file couldn't be read
+    Io(PathBuf, std::io::Error),
+    /// The config file couldn't be parsed
+    Parse(PathBuf, String),
+    /// The config was loaded, but some values are wrong
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "couldn't read {}: {e}", path.display()),
+            ConfigError::Parse(path, e) => write!(f, "couldn't parse {}: {e}", path.display()),
+            ConfigError::Invalid(problems) => {
+                write!(f, "invalid config:")?;
+                for problem in problems {
+                    write!(f, "\n  - {problem}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Loads the config from every source, in order of priority, and validates it.
+    pub fn load(args: &ConfigArgs) -> Result<Self, ConfigError> {
+        let mut config = Self::layered(args)?;
+        config.resolve_token()?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Loads the config from every source, in order of priority, without checking it. This is
+    /// enough for things that don't connect to discord (and so don't need a token).
+    pub fn layered(args: &ConfigArgs) -> Result<Self, ConfigError> {
+        let path = args
+            .config
+            .clone()
+            .or_else(|| std::env::var_os("NANO_CONFIG").map(PathBuf::from));
+
+        let mut config = match path {
+            Some(path) => Self::from_file(&path)?,
+            None => match DEFAULT_CONFIG_PATHS
+                .iter()
+                .map(Path::new)
+                .find(|p| p.exists())
+            {
+                Some(path) => Self::from_file(path)?,
+                None => Self::default(),
+            },
+        };
+
+        config.apply_env(std::env::vars())?;
+        config.apply_args(args);
+
+        Ok(config)
+    }
+
+    /// Reads a config file. The format is picked based on the file extension.
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_owned(), e))?;
+        let is_toml = path.extension().is_some_and(|ext| ext == "toml");
+
+        if is_toml {
+            toml::from_str(&text).map_err(|e| ConfigError::Parse(path.to_owned(), e.to_string()))
+        } else {
+            serde_yaml::from_str(&text)
+                .map_err(|e| ConfigError::Parse(path.to_owned(), e.to_string()))
+        }
+    }
+
+    /// Overrides values with any `NANO_*` variables in `vars`.
+    ///
+    /// List values (intents, guilds and owners) are comma separated.
+    pub fn apply_env(
+        &mut self,
+        vars: impl Iterator<Item = (String, String)>,
+    ) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+        let (mut token, mut token_file) = (None, None);
+
+        for (key, value) in vars {
+            match key.as_str() {
+                "NANO_TOKEN" => token = Some(value),
+                "NANO_TOKEN_FILE" => token_file = Some(value.into()),
+                "NANO_ACTIONS" => self.assets.actions = value.into(),
+                "NANO_SAY_HI" => self.assets.say_hi = value.into(),
+                "NANO_TRIGGERS" => self.assets.triggers = value.into(),
+                "NANO_LOG_DIR" => self.log_dir = value.into(),
+                "NANO_DATABASE" => self.database = Some(value.into()),
+                "NANO_INTENTS" => self.intents = split_list(&value).map(String::from).collect(),
+                "NANO_REGISTRATION" => match RegistrationMode::from_str(&value, true) {
+                    Ok(mode) => self.registration.mode = mode,
+                    Err(_) => problems.push(format!(
+                        "NANO_REGISTRATION: \"{value}\" is not one of global, guilds or both"
+                    )),
+                },
+                "NANO_GUILDS" => match parse_ids(&value) {
+                    Ok(ids) => self.registration.guilds = ids.map(GuildId::new).collect(),
+                    Err(e) => problems.push(format!("NANO_GUILDS: {e}")),
+                },
+                "NANO_OWNERS" => match parse_ids(&value) {
+                    Ok(ids) => self.owners = ids.map(UserId::new).collect(),
+                    Err(e) => problems.push(format!("NANO_OWNERS: {e}")),
+                },
+                _ => {}
+            }
+        }
+
+        self.override_token(token, token_file);
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+
+    /// Overrides values with any that were given on the command line.
+    pub fn apply_args(&mut self, args: &ConfigArgs) {
+        self.override_token(args.token.clone(), args.token_file.clone());
+
+        if let Some(actions) = &args.actions {
+            self.assets.actions = actions.clone();
+        }
+
+        if let Some(say_hi) = &args.say_hi {
+            self.assets.say_hi = say_hi.clone();
+        }
+
+        if let Some(triggers) = &args.triggers {
+            self.assets.triggers = triggers.clone();
+        }
+
+        if let Some(log_dir) = &args.log_dir {
+            self.log_dir = log_dir.clone();
+        }
+
+        if let Some(database) = &args.database {
+            self.database = Some(database.clone());
+        }
+
+        if let Some(intents) = &args.intents {
+            self.intents = intents.clone();
+        }
+
+        if let Some(mode) = args.registration {
+            self.registration.mode = mode;
+        }
+
+        if !args.guilds.is_empty() {
+            self.registration.guilds = args.guilds.clone();
+        }
+
+        if !args.owners.is_empty() {
+            self.owners = args.owners.clone();
+        }
+    }
+
+    /// Replaces the token with one from a higher layer. The token and token file go together, so
+    /// that a token file given on the command line isn't ignored for a token in the config file
+    fn override_token(&mut self, token: Option<String>, token_file: Option<PathBuf>) {
+        if token.is_some() || token_file.is_some() {
+            self.token = token;
+            self.token_file = token_file;
+        }
+    }
+
+    /// If no token was given directly, reads it from `token_file`.
+    pub(crate) fn resolve_token(&mut self) -> Result<(), ConfigError> {
+        if self.token.is_none() {
+            if let Some(path) = &self.token_file {
+                let token =
+                    fs::read_to_string(path).map_err(|e| ConfigError::Io(path.clone(), e))?;
+                self.token = Some(token.trim().to_owned());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks the config for mistakes, returning every problem that was found.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+
+        if self.token.as_deref().is_none_or(|t| t.trim().is_empty()) {
+            problems.push(
+                "no bot token was given (set `token` or `token_file`, NANO_TOKEN or --token)"
+                    .to_owned(),
+            );
+        }
+
+        for path in [
+            &self.assets.actions,
+            &self.assets.say_hi,
+            &self.assets.triggers,
+        ] {
+            if !path.is_file() {
+                problems.push(format!("asset file {} does not exist", path.display()));
+            }
+        }
+
+        for name in &self.intents {
+            if GatewayIntents::from_name(name).is_none() {
+                problems.push(format!("unknown gateway intent \"{name}\""));
+            }
+        }
+
+        if self.registration.mode != RegistrationMode::Global && self.registration.guilds.is_empty()
+        {
+            problems.push(
+                "commands are set to be registered in guilds, but no registration guilds were given"
+                    .to_owned(),
+            );
+        }
+
+        if self.commands.defer_after_ms >= 3000 {
+            problems.push(
+                "commands.defer_after_ms must be less than 3000, or interactions will expire"
+                    .to_owned(),
+            );
+        }
+
+        for name in &self.gifs.providers {
+            if !self.gifs.custom.contains_key(name)
+                && !gifs::builtin_names().contains(&name.as_str())
+            {
+                problems.push(format!("unknown gif provider \"{name}\""));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+
+    /// The bot token. This is always set once the config has been loaded.
+    pub fn token(&self) -> &str {
+        self.token.as_deref().unwrap_or_default()
+    }
+
+    /// The gateway intents to connect with. Unknown names are ignored (but are caught by
+    /// [Config::validate]).
+    pub fn gateway_intents(&self) -> GatewayIntents {
+        self.intents
+            .iter()
+            .filter_map(|name| GatewayIntents::from_name(name))
+            .fold(GatewayIntents::empty(), |acc, intent| acc | intent)
+    }
+}
+
+fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    value.split(',').map(str::trim).filter(|s| !s.is_empty())
+}
+
+fn parse_ids(value: &str) -> Result<impl Iterator<Item = u64>, String> {
+    let ids = split_list(value)
+        .map(|id| {
+            id.parse::<u64>()
+                .ok()
+                .filter(|&id| id != 0)
+                .ok_or_else(|| format!("\"{id}\" is not a valid id"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(ids.into_iter())
+}
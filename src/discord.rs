@@ -0,0 +1,212 @@
+//! Everything the bot asks discord to do.
+//!
+//! Commands and event handlers don't use serenity's [Http] directly, they go through a
+//! [DiscordApi]. When the bot is running that's just serenity ([SerenityApi]), but in tests it can
+//! be swapped for a fake that records what was sent, so commands can be run without a connection
+//! to discord.
+use std::sync::Arc;
+
+use serenity::all::{
+    Cache, ChannelId, Command, CommandId, Context, CreateCommand, CreateInteractionResponse,
+    CreateInteractionResponseFollowup, CreateMessage, EditInteractionResponse, GuildId, Http,
+    InteractionId, MessageId, ReactionType, User, UserId,
+};
+use serenity::async_trait;
+
+use crate::registration::Target;
+
+/// A shared handle to discord
+pub type Discord = Arc<dyn DiscordApi>;
+
+/// The requests the bot makes to discord
+#[async_trait]
+pub trait DiscordApi: Send + Sync {
+    async fn create_interaction_response(
+        &self,
+        interaction_id: InteractionId,
+        token: &str,
+        response: &CreateInteractionResponse,
+    ) -> serenity::Result<()>;
+
+    async fn edit_original_interaction_response(
+        &self,
+        token: &str,
+        edit: &EditInteractionResponse,
+    ) -> serenity::Result<()>;
+
+    async fn delete_original_interaction_response(&self, token: &str) -> serenity::Result<()>;
+
+    async fn create_followup_message(
+        &self,
+        token: &str,
+        followup: &CreateInteractionResponseFollowup,
+    ) -> serenity::Result<()>;
+
+    async fn send_message(
+        &self,
+        channel: ChannelId,
+        message: &CreateMessage,
+    ) -> serenity::Result<()>;
+
+    async fn create_reaction(
+        &self,
+        channel: ChannelId,
+        message: MessageId,
+        reaction: &ReactionType,
+    ) -> serenity::Result<()>;
+
+    /// The bot's own user
+    async fn current_user(&self) -> serenity::Result<User>;
+
+    async fn get_user(&self, user: UserId) -> serenity::Result<User>;
+
+    /// A user's nickname in a guild, if they have one
+    async fn nickname(&self, guild: GuildId, user: UserId) -> serenity::Result<Option<String>>;
+
+    /// The slash commands registered at `target`
+    async fn get_commands(&self, target: Target) -> serenity::Result<Vec<Command>>;
+
+    async fn create_command(&self, target: Target, command: &CreateCommand)
+        -> serenity::Result<()>;
+
+    async fn edit_command(
+        &self,
+        target: Target,
+        id: CommandId,
+        command: &CreateCommand,
+    ) -> serenity::Result<()>;
+
+    async fn delete_command(&self, target: Target, id: CommandId) -> serenity::Result<()>;
+}
+
+/// The real discord, through serenity. The cache is checked before making requests where it can
+/// be.
+#[derive(Clone)]
+pub struct SerenityApi {
+    http: Arc<Http>,
+    cache: Arc<Cache>,
+}
+
+impl SerenityApi {
+    pub fn new(http: Arc<Http>, cache: Arc<Cache>) -> Self {
+        Self { http, cache }
+    }
+}
+
+impl From<&Context> for SerenityApi {
+    fn from(ctx: &Context) -> Self {
+        Self::new(Arc::clone(&ctx.http), Arc::clone(&ctx.cache))
+    }
+}
+
+#[async_trait]
+impl DiscordApi for SerenityApi {
+    async fn create_interaction_response(
+        &self,
+        interaction_id: InteractionId,
+        token: &str,
+        response: &CreateInteractionResponse,
+    ) -> serenity::Result<()> {
+        self.http
+            .create_interaction_response(interaction_id, token, response, Vec::new())
+            .await
+    }
+
+    async fn edit_original_interaction_response(
+        &self,
+        token: &str,
+        edit: &EditInteractionResponse,
+    ) -> serenity::Result<()> {
+        self.http
+            .edit_original_interaction_response(token, edit, Vec::new())
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_original_interaction_response(&self, token: &str) -> serenity::Result<()> {
+        self.http.delete_original_interaction_response(token).await
+    }
+
+    async fn create_followup_message(
+        &self,
+        token: &str,
+        followup: &CreateInteractionResponseFollowup,
+    ) -> serenity::Result<()> {
+        self.http
+            .create_followup_message(token, followup, Vec::new())
+            .await?;
+        Ok(())
+    }
+
+    async fn send_message(
+        &self,
+        channel: ChannelId,
+        message: &CreateMessage,
+    ) -> serenity::Result<()> {
+        self.http.send_message(channel, Vec::new(), message).await?;
+        Ok(())
+    }
+
+    async fn create_reaction(
+        &self,
+        channel: ChannelId,
+        message: MessageId,
+        reaction: &ReactionType,
+    ) -> serenity::Result<()> {
+        self.http.create_reaction(channel, message, reaction).await
+    }
+
+    async fn current_user(&self) -> serenity::Result<User> {
+        Ok(self.http.get_current_user().await?.into())
+    }
+
+    async fn get_user(&self, user: UserId) -> serenity::Result<User> {
+        user.to_user((&self.cache, &*self.http)).await
+    }
+
+    async fn nickname(&self, guild: GuildId, user: UserId) -> serenity::Result<Option<String>> {
+        let member = guild.member((&self.cache, &*self.http), user).await?;
+        Ok(member.nick)
+    }
+
+    async fn get_commands(&self, target: Target) -> serenity::Result<Vec<Command>> {
+        match target {
+            Target::Global => self.http.get_global_commands().await,
+            Target::Guild(guild) => self.http.get_guild_commands(guild).await,
+        }
+    }
+
+    async fn create_command(
+        &self,
+        target: Target,
+        command: &CreateCommand,
+    ) -> serenity::Result<()> {
+        match target {
+            Target::Global => self.http.create_global_command(command).await?,
+            Target::Guild(guild) => self.http.create_guild_command(guild, command).await?,
+        };
+
+        Ok(())
+    }
+
+    async fn edit_command(
+        &self,
+        target: Target,
+        id: CommandId,
+        command: &CreateCommand,
+    ) -> serenity::Result<()> {
+        match target {
+            Target::Global => self.http.edit_global_command(id, command).await?,
+            Target::Guild(guild) => self.http.edit_guild_command(guild, id, command).await?,
+        };
+
+        Ok(())
+    }
+
+    async fn delete_command(&self, target: Target, id: CommandId) -> serenity::Result<()> {
+        match target {
+            Target::Global => self.http.delete_global_command(id).await,
+            Target::Guild(guild) => self.http.delete_guild_command(guild, id).await,
+        }
+    }
+}
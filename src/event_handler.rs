@@ -1,25 +1,66 @@
+use crate::assets::{AssetError, Assets};
+use crate::commands::action::Answers;
+use crate::commands::cooldown::Cooldowns;
+use crate::commands::error::{report_error, CommandError, CommandResult};
+use crate::commands::responder::{Reply, Responder};
 use crate::commands::say_hi::SayHiData;
-use crate::commands::{self, CommandFn};
+use crate::commands::{self, CommandDetails};
 use crate::commands::{action::ActionCommandData, help::HelpDetails};
-use crate::utils::SharedStopwatch;
-use serenity::all::{ComponentInteraction, ComponentInteractionDataKind, Interaction, MessageId};
+use crate::components::{ComponentEvent, ComponentRouter};
+use crate::config::Config;
+use crate::discord::{Discord, DiscordApi, SerenityApi};
+use crate::gifs::GifProviders;
+use crate::guild_settings::GuildSettingsStore;
+use crate::registration;
+use crate::storage::{Repository, SqliteStore, StorageError};
+use crate::triggers::{TriggerAction, Triggers};
+use crate::utils::RecentInteractions;
+use rand::seq::SliceRandom;
+use rand::thread_rng;
+use serenity::all::{
+    ChannelId, CommandInteraction, CreateAllowedMentions, CreateAutocompleteResponse,
+    CreateInteractionResponse, CreateMessage, GuildId, Interaction, ReactionType,
+};
 use serenity::async_trait;
 use serenity::model::channel::Message;
 use serenity::model::gateway::Ready;
 use serenity::prelude::*;
+use serenity::utils::MessageBuilder;
 use std::collections::HashMap;
-use std::fs;
+use std::fmt;
 use std::ops::Deref;
 use std::sync::Arc;
-use tokio::sync::mpsc;
-use tokio::sync::mpsc::error::SendError;
-use tracing::{error, info, instrument, span, Instrument, Level};
+use tracing::{error, info, instrument, span, warn, Instrument, Level};
 
-#[cfg(debug_assertions)]
-use serenity::all::GuildId;
+/// Why a [Handler] couldn't be made
+#[derive(Debug)]
+pub enum SetupError {
+    Assets(AssetError),
+    Storage(StorageError),
+}
+
+impl fmt::Display for SetupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SetupError::Assets(e) => write!(f, "couldn't load assets: {e}"),
+            SetupError::Storage(e) => write!(f, "couldn't open the database: {e}"),
+        }
+    }
+}
 
-#[cfg(not(debug_assertions))]
-use serenity::all::Command;
+impl std::error::Error for SetupError {}
+
+impl From<AssetError> for SetupError {
+    fn from(e: AssetError) -> Self {
+        SetupError::Assets(e)
+    }
+}
+
+impl From<StorageError> for SetupError {
+    fn from(e: StorageError) -> Self {
+        SetupError::Storage(e)
+    }
+}
 
 #[derive(Clone)]
 pub struct Handler {
@@ -27,11 +68,145 @@ pub struct Handler {
 }
 
 impl Handler {
-    pub fn new() -> Handler {
-        Self {
-            inner: Arc::new(HandlerInner::new()),
+    pub fn new(config: Config) -> Result<Handler, SetupError> {
+        Ok(Self {
+            inner: Arc::new(HandlerInner::new(config)?),
+        })
+    }
+
+    /// Runs a command, letting the user know if it fails
+    pub async fn dispatch(&self, discord: Discord, cmd: CommandInteraction) {
+        info!("recieved command");
+        self.recent_interactions
+            .record(cmd.channel_id, cmd.user.id)
+            .await;
+
+        let name = cmd.data.name.clone();
+        let responder = Responder::new(Arc::clone(&discord), &cmd);
+
+        // If the command takes too long, send a deferred response so the interaction doesn't
+        // expire. The command's responder will pick up where this left off.
+        let deferral = responder.defer_after(self.config.commands.defer_after());
+
+        // The command is cloned out so that the map isn't locked while it runs
+        let command = self.commands.read().await.get(&name).cloned();
+        let result = match command {
+            Some(details) => {
+                self.run_command(discord, &details, cmd, responder.clone())
+                    .await
+            }
+            None => Err(CommandError::UnknownCommand(name)),
+        };
+
+        match result {
+            Ok(()) => {}
+
+            // Not really errors, the command just can't be used right now
+            Err(
+                e @ (CommandError::CoolingDown(_)
+                | CommandError::Disabled
+                | CommandError::WrongChannel(_)),
+            ) => {
+                info!("{e}");
+                let reply = Reply::new().content(e.user_message()).ephemeral(true);
+                if let Err(e) = responder.respond(reply).await {
+                    error!("couldn't explain why the command wasn't run: {e}");
+                }
+            }
+
+            Err(e) => report_error(&responder, e).await,
+        }
+
+        deferral.finish().await;
+    }
+
+    /// Runs a command, if the guild's settings and its cooldowns allow it
+    async fn run_command(
+        &self,
+        discord: Discord,
+        details: &CommandDetails,
+        cmd: CommandInteraction,
+        responder: Responder,
+    ) -> CommandResult {
+        self.check_guild_settings(details, cmd.guild_id, cmd.channel_id)
+            .await?;
+
+        self.cooldowns
+            .try_use(&details.name, &details.cooldowns, &cmd)
+            .map_err(CommandError::CoolingDown)?;
+
+        let command = &details.command;
+        command(discord, self.clone(), cmd, responder).await
+    }
+
+    /// Checks that a guild's settings let a command be used in a channel
+    async fn check_guild_settings(
+        &self,
+        details: &CommandDetails,
+        guild: Option<GuildId>,
+        channel: ChannelId,
+    ) -> CommandResult {
+        match guild {
+            Some(guild) => self
+                .guild_settings
+                .get(guild)
+                .await?
+                .check(details, channel),
+            None => Ok(()),
         }
     }
+
+    /// Checks that a command could be used in a channel, for components that do the same thing
+    /// as it (like the buttons for answering an action)
+    pub async fn check_command_allowed(
+        &self,
+        name: &str,
+        guild: Option<GuildId>,
+        channel: ChannelId,
+    ) -> CommandResult {
+        let details = self
+            .commands
+            .read()
+            .await
+            .get(name)
+            .cloned()
+            .ok_or_else(|| CommandError::UnknownCommand(name.to_owned()))?;
+
+        self.check_guild_settings(&details, guild, channel).await
+    }
+
+    /// Suggests values for the option a user is typing in, if the command knows how
+    pub async fn dispatch_autocomplete(&self, discord: Discord, cmd: CommandInteraction) {
+        let command = self.commands.read().await.get(&cmd.data.name).cloned();
+        let autocomplete = command.and_then(|details| details.autocomplete.clone());
+
+        let mut choices = match autocomplete {
+            Some(autocomplete) => {
+                autocomplete(Arc::clone(&discord), self.clone(), cmd.clone()).await
+            }
+            None => {
+                warn!("command has no autocomplete, suggesting nothing");
+                Vec::new()
+            }
+        };
+
+        // Discord won't show any more than this
+        choices.truncate(25);
+
+        let response = CreateAutocompleteResponse::new().set_choices(choices);
+        let response = CreateInteractionResponse::Autocomplete(response);
+        if let Err(e) = discord
+            .create_interaction_response(cmd.id, &cmd.token, &response)
+            .await
+        {
+            error!("couldn't send autocomplete suggestions: {e}");
+        }
+    }
+
+    /// Sends a button, select menu or modal interaction to whatever it belongs to
+    pub async fn dispatch_component(&self, discord: Discord, event: ComponentEvent) {
+        self.components.route(discord, self.clone(), event).await;
+    }
 }
 
 impl Deref for Handler {
@@ -42,166 +217,254 @@ impl Deref for Handler {
     }
 }
 
-#[derive(Default)]
 pub struct HandlerInner {
-    pub http_client: reqwest::Client,
-    commands: RwLock<HashMap<String, CommandFn>>,
-
-    // A (static) list of all the data associated with action commands
-    // read from assets/actions.yaml
-    pub actions: Vec<ActionCommandData>,
-    pub say_hi_data: Vec<SayHiData>,
+    pub config: Config,
+    pub gifs: GifProviders,
+    commands: RwLock<HashMap<String, Arc<CommandDetails>>>,
+    cooldowns: Cooldowns,
+    // Everything that's kept between restarts. The connection pool is shared by everything
+    // that holds onto this
+    pub storage: Arc<dyn Repository>,
+    // Which commands each guild has turned off or restricted, changed with /config
+    pub guild_settings: GuildSettingsStore,
+
+    // A list of all the data associated with action commands
+    // read from assets/actions.yaml (and reloaded with /reload or SIGHUP)
+    pub actions: RwLock<Vec<ActionCommandData>>,
+    pub say_hi_data: RwLock<Vec<SayHiData>>,
+    // Rules for responding to plain messages, from assets/triggers.yaml
+    pub triggers: RwLock<Arc<Triggers>>,
+    // Held while reloading, so two reloads can't interleave
+    reload_lock: Mutex<()>,
     pub help_data: RwLock<Vec<HelpDetails>>,
-    // Keep track of how long it's been since the bot was interacted with
+    // Keep track of where the bot was recently interacted with
     // to make responses to "good bot" seem a bit more normal
-    last_interaction: SharedStopwatch,
+    pub recent_interactions: RecentInteractions,
+    // Action messages whose buttons have been pressed, so they can't be answered twice
+    pub answers: Answers,
 
-    pub button_event_tx: RwLock<HashMap<MessageId, mpsc::Sender<ComponentInteraction>>>,
+    // Where button, select menu and modal interactions get sent
+    components: ComponentRouter,
 }
 
 impl HandlerInner {
-    pub fn new() -> Self {
-        let actions =
-            serde_yaml::from_str(&fs::read_to_string("assets/actions.yaml").unwrap()).unwrap();
-        let say_hi_data =
-            serde_yaml::from_str(&fs::read_to_string("assets/say_hi.yaml").unwrap()).unwrap();
-
-        Self {
-            actions,
-            say_hi_data,
-            ..Default::default()
-        }
+    pub fn new(config: Config) -> Result<Self, SetupError> {
+        let assets = Assets::load(&config)?;
+        let storage: Arc<dyn Repository> = match &config.database {
+            Some(path) => Arc::new(SqliteStore::open(path)?),
+            None => Arc::new(SqliteStore::in_memory()?),
+        };
+        let gifs = GifProviders::new(&config.gifs);
+        let recent_interactions =
+            RecentInteractions::new(config.interactions.window(), config.interactions.per_user);
+
+        Ok(Self {
+            config,
+            gifs,
+            commands: RwLock::default(),
+            cooldowns: Cooldowns::default(),
+            answers: Answers::default(),
+            guild_settings: GuildSettingsStore::new(Arc::clone(&storage)),
+            storage,
+            actions: RwLock::new(assets.actions),
+            say_hi_data: RwLock::new(assets.say_hi),
+            triggers: RwLock::new(Arc::new(assets.triggers)),
+            reload_lock: Mutex::default(),
+            help_data: RwLock::default(),
+            recent_interactions,
+            components: commands::component_router(),
+        })
     }
-}
-
-#[async_trait]
-impl EventHandler for Handler {
-    // This instrument macro is just for logging
-    // it allows the log to contain some info about the message
-    #[instrument(
-        skip_all,
-        fields(
-            author = %msg.author,
-            guild = ?msg.guild_id,
-        )
-    )]
-    async fn message(&self, ctx: Context, msg: Message) {
-        if msg.content.starts_with("->melo") {
-            info!("recieved melo");
-            if let Err(e) = msg.react(&ctx.http, '🍈').await {
-                error!("couldnt react to melo message: {e}");
-            }
-            self.inner.last_interaction.set_now().await;
-        }
 
-        if self
-            .inner
-            .last_interaction
-            .get()
-            .await
-            .is_some_and(|t| t.elapsed().as_secs() < 60)
-        {
-            // Respond to "good/bad bot" messages if nano has done something within the last
-            // 60 seconds
-            let is_good_bot = msg
-                .content
-                .to_lowercase()
-                .trim_matches(|c: char| !c.is_ascii_alphabetic())
-                == "good bot";
-
-            let is_bad_bot = msg
-                .content
-                .to_lowercase()
-                .trim_matches(|c: char| !c.is_ascii_alphabetic())
-                == "bad bot";
-
-            if is_good_bot {
-                // Once she responds to a good/bad bot message, she probably shouldnt respond to
-                // another until she does some other helpful thing
-                // so reset the stopwatch
-                self.inner.last_interaction.unset().await;
-
-                if let Err(e) = msg
-                    .channel_id
-                    .say(&ctx.http, "I'm not a robot! But thank you.")
-                    .await
-                {
-                    error!("couldn't send thank you message: {e}");
+    /// Builds every command from the current assets, and registers them with discord.
+    ///
+    /// Returns a summary of what changed in each place the commands are registered.
+    pub async fn register_commands(&self, discord: &dyn DiscordApi) -> Vec<String> {
+        // Where the commands go is decided by the config. Only commands that differ from what
+        // discord already has get sent, so restarting the bot doesn't spam the API.
+        let mut commands = commands::builtin();
+        commands.extend(commands::action::register(&self.actions.read().await));
+
+        *self.help_data.write().await = commands.iter().map(|cmd| cmd.help.clone()).collect();
+
+        let registrations = commands
+            .iter()
+            .map(|cmd| cmd.registration.clone())
+            .collect::<Vec<_>>();
+
+        let mut summary = Vec::new();
+
+        for target in self.config.registration.targets() {
+            let line = match registration::sync(discord, target, &registrations).await {
+                Ok(diff) if diff.is_empty() => format!("commands in {target} are up to date"),
+                Ok(diff) => format!("synced commands in {target}: {diff}"),
+                Err(e) => {
+                    error!("error registering commands in {target}: {e}");
+                    format!("error registering commands in {target}")
                 }
-            } else if is_bad_bot {
-                self.inner.last_interaction.unset().await;
+            };
 
-                let gif_url =
-                    "https://media1.tenor.com/m/02kmUuBVE9IAAAAd/watch-yo-tone-nichijou.gif";
+            info!("{line}");
+            summary.push(line);
+        }
 
-                if let Err(e) = msg.reply_ping(&ctx.http, gif_url).await {
-                    error!("couldnt slap user: {e}");
+        // Commands registered under a different mode would otherwise show up twice, or linger
+        for target in self.config.registration.unused_targets() {
+            match registration::sync(discord, target, &[]).await {
+                Ok(diff) if diff.is_empty() => {}
+                Ok(diff) => {
+                    let line = format!("removed unused commands from {target}: {diff}");
+                    info!("{line}");
+                    summary.push(line);
                 }
+                Err(e) => error!("error removing unused commands from {target}: {e}"),
             }
         }
+
+        // Swap out the whole map, so that commands which no longer exist are removed
+        *self.commands.write().await = commands
+            .into_iter()
+            .map(|cmd| {
+                info!("registered command \"{}\"", cmd.name);
+                (cmd.name.clone(), Arc::new(cmd))
+            })
+            .collect();
+
+        summary
     }
 
-    #[instrument(skip_all)]
-    async fn ready(&self, ctx: Context, ready: Ready) {
-        info!("{} is connected!", ready.user.name);
+    /// Reloads the asset files and re-registers any commands that changed.
+    ///
+    /// The new assets are checked first. If they're invalid, the old ones are kept.
+    pub async fn reload(&self, discord: &dyn DiscordApi) -> Result<Vec<String>, AssetError> {
+        let _guard = self.reload_lock.lock().await;
+
+        let assets = Assets::load(&self.config)?;
+        let mut summary = vec![format!(
+            "loaded {} actions, {} say hi messages and {} triggers",
+            assets.actions.len(),
+            assets.say_hi.len(),
+            assets.triggers.rules().len(),
+        )];
+
+        *self.actions.write().await = assets.actions;
+        *self.say_hi_data.write().await = assets.say_hi;
+        *self.triggers.write().await = Arc::new(assets.triggers);
+
+        summary.extend(self.register_commands(discord).await);
+        Ok(summary)
+    }
 
-        // Registering commands
-        // For debug runs, the commands will just be registered in my test server as server-scoped
-        // commands update instantly. For release runs, this will be global so that the commands
-        // may be used anywhere.
+    /// Responds to plain messages, using the rules in triggers.yaml
+    pub async fn handle_message(&self, discord: &dyn DiscordApi, msg: &Message) {
+        // Bots (Nano included) don't set off triggers, or they could set each other off forever
+        if msg.author.bot {
+            return;
+        }
 
-        let mut commands = vec![commands::help::register(), commands::say_hi::register()];
+        // Cloned out, so a reload doesn't have to wait for this message to be dealt with
+        let triggers = Arc::clone(&*self.triggers.read().await);
+        let (channel, author) = (msg.channel_id, msg.author.id);
 
-        commands.extend(commands::action::register(&self.inner.actions));
+        for (i, rule) in triggers.matching(&msg.content, msg.guild_id) {
+            if rule.requires_interaction
+                && !self.recent_interactions.is_recent(channel, author).await
+            {
+                continue;
+            }
 
-        *self.inner.help_data.write().await = commands.iter().map(|cmd| cmd.help.clone()).collect();
+            if !triggers.cool_down(i, channel) {
+                info!("trigger \"{}\" is cooling down", rule.name);
+                continue;
+            }
 
-        cfg_if::cfg_if! {
-            if #[cfg(debug_assertions)] {
-                // Register the commands in my test server if this is a debug run
-                let test_guild_id = GuildId::new(
-                    fs::read_to_string("test_guild_id.txt")
-                        .expect("couldnt read test guild id")
-                        .trim()
-                        .parse()
-                        .expect("guild id must be an integer"),
-                );
+            info!("trigger \"{}\" fired", rule.name);
 
-                let register_result = test_guild_id
-                    .set_commands(
-                        ctx,
-                        commands.iter().map(|cmd| cmd.registration.clone()).collect(),
-                    )
-                    .await;
+            // Once she responds to something like a good/bad bot message, she probably shouldnt
+            // respond to another until she does some other helpful thing
+            if rule.requires_interaction {
+                self.recent_interactions.clear(channel, author).await;
+            }
 
-                if let Err(e) = register_result {
-                    error!("error creating server test commands: {e}");
-                }
-            } else {
-                // If this is a release run, register them globally
-                let register_result =
-                    Command::set_global_commands(
-                        ctx,
-                        commands.iter().map(|cmd| cmd.registration.clone()).collect(),
-                    )
-                    .await;
+            if rule.counts_as_interaction {
+                self.recent_interactions.record(channel, author).await;
+            }
 
-                if let Err(e) = register_result {
-                    error!("error creating global commands: {e}");
+            for action in &rule.actions {
+                if let Err(e) = run_trigger_action(discord, msg, action).await {
+                    error!("couldn't respond to trigger \"{}\": {e}", rule.name);
                 }
             }
         }
+    }
+}
 
-        for cmd in commands {
-            info!("registered command \"{}\"", cmd.name);
-            self.inner
-                .commands
-                .write()
-                .await
-                .insert(cmd.name, cmd.command);
+async fn run_trigger_action(
+    discord: &dyn DiscordApi,
+    msg: &Message,
+    action: &TriggerAction,
+) -> serenity::Result<()> {
+    let choose = |options: &[String]| options.choose(&mut thread_rng()).cloned();
+    let mention = MessageBuilder::new().mention(&msg.author).build();
+
+    match action {
+        TriggerAction::React(emoji) => {
+            // Emoji are checked when triggers.yaml is loaded
+            if let Ok(reaction) = ReactionType::try_from(emoji.as_str()) {
+                discord
+                    .create_reaction(msg.channel_id, msg.id, &reaction)
+                    .await?;
+            }
+        }
+
+        TriggerAction::Say(messages) => {
+            if let Some(message) = choose(messages) {
+                let message = CreateMessage::new().content(message.replace("<user>", &mention));
+                discord.send_message(msg.channel_id, &message).await?;
+            }
+        }
+
+        TriggerAction::Reply(messages) | TriggerAction::Gif(messages) => {
+            let content = choose(messages).map(|message| message.replace("<user>", &mention));
+
+            if let Some(content) = content {
+                let reply = CreateMessage::new()
+                    .content(content)
+                    .reference_message(msg)
+                    .allowed_mentions(CreateAllowedMentions::new().replied_user(true));
+
+                discord.send_message(msg.channel_id, &reply).await?;
+            }
         }
     }
 
+    Ok(())
+}
+
+#[async_trait]
+impl EventHandler for Handler {
+    // This instrument macro is just for logging
+    // it allows the log to contain some info about the message
+    #[instrument(
+        skip_all,
+        fields(
+            author = %msg.author,
+            guild = ?msg.guild_id,
+        )
+    )]
+    async fn message(&self, ctx: Context, msg: Message) {
+        self.inner
+            .handle_message(&SerenityApi::from(&ctx), &msg)
+            .await;
+    }
+
+    #[instrument(skip_all)]
+    async fn ready(&self, ctx: Context, ready: Ready) {
+        info!("{} is connected!", ready.user.name);
+
+        self.inner.register_commands(&SerenityApi::from(&ctx)).await;
+    }
+
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
         match interaction {
             Interaction::Command(cmd) => {
@@ -216,49 +479,55 @@ impl EventHandler for Handler {
                     options = ?cmd.data.options
                 );
 
-                // the whole async move - instrument - await thing is also just for logging purposes
-                async move {
-                    info!("recieved command");
-                    self.inner.last_interaction.set_now().await;
+                // the whole instrument - await thing is also just for logging purposes
+                let discord: Discord = Arc::new(SerenityApi::from(&ctx));
+                self.dispatch(discord, cmd).instrument(span).await;
+            }
 
-                    let name = cmd.data.name.as_str();
+            Interaction::Autocomplete(cmd) => {
+                let span = span!(
+                    Level::INFO, "autocomplete",
+                    user = cmd.user.name,
+                    guild = ?cmd.guild_id,
+                    cmd = cmd.data.name,
+                );
 
-                    match self.inner.commands.read().await.get(name) {
-                        Some(command) => command(ctx.clone(), self.clone(), cmd).await,
-                        None => error!("Command is unrecognised: {name}"),
-                    }
-                }
-                .instrument(span)
-                .await;
+                let discord: Discord = Arc::new(SerenityApi::from(&ctx));
+                self.dispatch_autocomplete(discord, cmd)
+                    .instrument(span)
+                    .await;
             }
 
             Interaction::Component(interaction) => {
                 let span = span!(
-                    Level::INFO, "command",
+                    Level::INFO, "component",
                     user = interaction.user.name,
                     guild = ?interaction.guild_id,
                     id = interaction.data.custom_id,
                 );
 
-                async move {
-                    if matches!(interaction.data.kind, ComponentInteractionDataKind::Button) {
-                        info!("recieved button interaction. transmitting it to handler thread");
-                        let txs = self.inner.button_event_tx.read().await;
-                        if let Some(tx) = txs.get(&interaction.message.id) {
-                            if let Err(SendError(ev)) = tx.send(interaction.clone()).await {
-                                error!(
-                                    "couldn't send button event ({}) to reciever",
-                                    ev.data.custom_id
-                                );
-                                drop(txs);
-                                let mut txs = self.inner.button_event_tx.write().await;
-                                txs.remove(&interaction.message.id);
-                            }
-                        }
-                    }
-                }
-                .instrument(span)
-                .await;
+                info!(parent: &span, "recieved component interaction");
+                let discord: Discord = Arc::new(SerenityApi::from(&ctx));
+                let event = ComponentEvent::Component(Box::new(interaction));
+                self.dispatch_component(discord, event)
+                    .instrument(span)
+                    .await;
+            }
+
+            Interaction::Modal(interaction) => {
+                let span = span!(
+                    Level::INFO, "modal",
+                    user = interaction.user.name,
+                    guild = ?interaction.guild_id,
+                    id = interaction.data.custom_id,
+                );
+
+                info!(parent: &span, "recieved modal submission");
+                let discord: Discord = Arc::new(SerenityApi::from(&ctx));
+                let event = ComponentEvent::Modal(Box::new(interaction));
+                self.dispatch_component(discord, event)
+                    .instrument(span)
+                    .await;
             }
 
             _ => {}
@@ -0,0 +1,136 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Deserialize;
+use serde_json::Value;
+use serenity::async_trait;
+use tracing::{info, instrument};
+
+use super::{GifError, GifProvider, GifRequest};
+
+/// Gets gifs from an api that returns json, e.g. nekos.best, by looking up the url at a given
+/// path in the response
+pub struct JsonProvider {
+    client: reqwest::Client,
+    url: String,
+    field: FieldPath,
+}
+
+impl JsonProvider {
+    pub fn new(client: reqwest::Client, url: String, field: FieldPath) -> Self {
+        Self { client, url, field }
+    }
+}
+
+#[async_trait]
+impl GifProvider for JsonProvider {
+    #[instrument(skip_all, fields(kind = request.kind))]
+    async fn get_gif(&self, request: GifRequest<'_>) -> Result<String, GifError> {
+        let url = self.url.replace("{kind}", request.kind);
+        info!("sending request to {url}");
+
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await?;
+
+        self.field
+            .get(&response)
+            .and_then(Value::as_str)
+            .map(str::to_owned)
+            .ok_or_else(|| GifError::MissingField(self.field.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// A path to a value inside a json object, written like `results[0].url`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct FieldPath(Vec<Segment>);
+
+impl FieldPath {
+    /// Looks up the value at this path, if there is one
+    pub fn get<'a>(&self, mut value: &'a Value) -> Option<&'a Value> {
+        for segment in &self.0 {
+            value = match segment {
+                Segment::Key(key) => value.get(key)?,
+                Segment::Index(i) => value.get(i)?,
+            };
+        }
+
+        Some(value)
+    }
+}
+
+impl FromStr for FieldPath {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut segments = Vec::new();
+
+        for part in s.split('.') {
+            if part.is_empty() {
+                return Err(format!("invalid field path \"{s}\""));
+            }
+
+            let (key, mut indices) = match part.find('[') {
+                Some(pos) => part.split_at(pos),
+                None => (part, ""),
+            };
+
+            if !key.is_empty() {
+                segments.push(Segment::Key(key.to_owned()));
+            }
+
+            while let Some(rest) = indices.strip_prefix('[') {
+                let (index, rest) = rest
+                    .split_once(']')
+                    .ok_or_else(|| format!("unclosed bracket in field path \"{s}\""))?;
+
+                let index = index
+                    .parse()
+                    .map_err(|_| format!("\"{index}\" is not a valid index in \"{s}\""))?;
+
+                segments.push(Segment::Index(index));
+                indices = rest;
+            }
+
+            if !indices.is_empty() {
+                return Err(format!("invalid field path \"{s}\""));
+            }
+        }
+
+        Ok(Self(segments))
+    }
+}
+
+impl TryFrom<String> for FieldPath {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                Segment::Key(key) if i == 0 => write!(f, "{key}")?,
+                Segment::Key(key) => write!(f, ".{key}")?,
+                Segment::Index(index) => write!(f, "[{index}]")?,
+            }
+        }
+
+        Ok(())
+    }
+}
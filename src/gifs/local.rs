@@ -0,0 +1,18 @@
+use rand::seq::SliceRandom;
+use serenity::async_trait;
+
+use super::{GifError, GifProvider, GifRequest};
+
+/// Picks a random gif from the action's `gifs` list in actions.yaml
+pub struct LocalProvider;
+
+#[async_trait]
+impl GifProvider for LocalProvider {
+    async fn get_gif(&self, request: GifRequest<'_>) -> Result<String, GifError> {
+        request
+            .gifs
+            .choose(&mut rand::thread_rng())
+            .cloned()
+            .ok_or(GifError::NoGifs)
+    }
+}
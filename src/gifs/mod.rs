@@ -0,0 +1,220 @@
+//! Sources of gifs for action commands.
+//!
+//! Every source implements [GifProvider]. Providers are given names in the config (a few are
+//! built in), and action commands try them in order until one returns a gif. The order can be
+//! set for all actions in the config, or per action in actions.yaml.
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::Deserialize;
+use serenity::async_trait;
+use tracing::{info, warn};
+
+use crate::config::GifConfig;
+
+mod json;
+mod local;
+mod otakugifs;
+mod resilience;
+
+pub use json::{FieldPath, JsonProvider};
+pub use local::LocalProvider;
+pub use otakugifs::{OtakugifsProvider, OTAKUGIFS_REACTIONS};
+pub use resilience::{Guarded, MetricsSnapshot, RequestPolicy};
+
+/// The name of the built in otakugifs provider
+pub const OTAKUGIFS: &str = "otakugifs";
+/// The name of the built in provider that uses each action's `gifs` list
+pub const LOCAL: &str = "local";
+
+/// What the action command is asking a provider for
+#[derive(Debug, Clone, Copy)]
+pub struct GifRequest<'a> {
+    /// The kind of action, e.g. "hug"
+    pub kind: &'a str,
+    /// The gifs listed for this action in actions.yaml
+    pub gifs: &'a [String],
+}
+
+#[derive(Debug)]
+pub enum GifError {
+    Http(reqwest::Error),
+    /// The response didn't have a url where we expected one
+    MissingField(String),
+    /// The provider has no gifs for this kind of action
+    NoGifs,
+    /// The request took too long
+    Timeout,
+    /// The provider has been failing, so it wasn't asked
+    CircuitOpen,
+}
+
+impl GifError {
+    /// Whether the request might work if it was tried again
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            GifError::Http(e) => match e.status() {
+                Some(status) => status.is_server_error() || status.as_u16() == 429,
+                None => e.is_timeout() || e.is_connect() || e.is_request(),
+            },
+            GifError::Timeout => true,
+            GifError::MissingField(_) | GifError::NoGifs | GifError::CircuitOpen => false,
+        }
+    }
+}
+
+impl fmt::Display for GifError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GifError::Http(e) => write!(f, "http error: {e}"),
+            GifError::MissingField(field) => write!(f, "response had no string at \"{field}\""),
+            GifError::NoGifs => write!(f, "no gifs available"),
+            GifError::Timeout => write!(f, "request timed out"),
+            GifError::CircuitOpen => write!(f, "provider is failing, skipped"),
+        }
+    }
+}
+
+impl std::error::Error for GifError {}
+
+impl From<reqwest::Error> for GifError {
+    fn from(e: reqwest::Error) -> Self {
+        GifError::Http(e)
+    }
+}
+
+/// Something that can find a gif url for an action
+#[async_trait]
+pub trait GifProvider: Send + Sync {
+    async fn get_gif(&self, request: GifRequest<'_>) -> Result<String, GifError>;
+}
+
+/// The definition of a provider in the config file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum ProviderConfig {
+    /// The otakugifs api, or anything that looks like it
+    Otakugifs {
+        #[serde(default = "otakugifs::default_url")]
+        url: String,
+    },
+    /// Picks from the action's gifs list
+    Local,
+    /// Any api that returns a gif url somewhere in a json object
+    Json {
+        /// The url to request. `{kind}` is replaced by the kind of action
+        url: String,
+        /// Where the url is in the response, e.g. `results[0].url`
+        field: FieldPath,
+    },
+}
+
+impl ProviderConfig {
+    fn build(&self, client: &reqwest::Client) -> Box<dyn GifProvider> {
+        match self {
+            ProviderConfig::Otakugifs { url } => {
+                Box::new(OtakugifsProvider::new(client.clone(), url.clone()))
+            }
+            ProviderConfig::Local => Box::new(LocalProvider),
+            ProviderConfig::Json { url, field } => Box::new(JsonProvider::new(
+                client.clone(),
+                url.clone(),
+                field.clone(),
+            )),
+        }
+    }
+}
+
+/// Every gif provider the bot knows about, by name
+pub struct GifProviders {
+    providers: HashMap<String, Guarded>,
+    default_order: Vec<String>,
+}
+
+impl GifProviders {
+    /// Sets up the built in providers, plus any extra ones defined in the config.
+    ///
+    /// Providers defined in the config replace built in ones with the same name.
+    pub fn new(config: &GifConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .connect_timeout(config.policy.timeout())
+            .build()
+            .unwrap_or_default();
+
+        let builtin = [
+            (
+                OTAKUGIFS,
+                ProviderConfig::Otakugifs {
+                    url: otakugifs::default_url(),
+                },
+            ),
+            (LOCAL, ProviderConfig::Local),
+        ];
+
+        let providers = builtin
+            .iter()
+            .map(|(name, provider)| (name.to_string(), provider))
+            .chain(
+                config
+                    .custom
+                    .iter()
+                    .map(|(name, provider)| (name.clone(), provider)),
+            )
+            .map(|(name, provider)| {
+                let guarded =
+                    Guarded::new(name.clone(), provider.build(&client), config.policy.clone());
+                (name, guarded)
+            })
+            .collect();
+
+        Self {
+            providers,
+            default_order: config.providers.clone(),
+        }
+    }
+
+    /// Tries each provider in `order` (or the default order if None) until one of them gives
+    /// us a gif. Returns None if every provider failed.
+    pub async fn find(&self, request: GifRequest<'_>, order: Option<&[String]>) -> Option<String> {
+        for name in order.unwrap_or(&self.default_order) {
+            let Some(provider) = self.providers.get(name) else {
+                warn!("unknown gif provider \"{name}\"");
+                continue;
+            };
+
+            match provider.get_gif(request).await {
+                Ok(url) => {
+                    info!("got gif from {name}");
+                    return Some(url);
+                }
+                Err(GifError::NoGifs) => {}
+                Err(e) => warn!("couldnt get gif from {name}: {e}"),
+            }
+        }
+
+        None
+    }
+
+    /// The metrics of each provider, sorted by name
+    pub fn metrics(&self) -> Vec<(&str, MetricsSnapshot)> {
+        let mut metrics = self
+            .providers
+            .iter()
+            .map(|(name, provider)| (name.as_str(), provider.metrics().snapshot()))
+            .collect::<Vec<_>>();
+
+        metrics.sort_by_key(|(name, _)| *name);
+        metrics
+    }
+}
+
+impl Default for GifProviders {
+    fn default() -> Self {
+        Self::new(&GifConfig::default())
+    }
+}
+
+/// The names of the providers that are always available
+pub fn builtin_names() -> [&'static str; 2] {
+    [OTAKUGIFS, LOCAL]
+}
@@ -0,0 +1,119 @@
+use serde::Deserialize;
+use serenity::async_trait;
+use tracing::{info, instrument};
+
+use super::{GifError, GifProvider, GifRequest};
+
+/// Every reaction otakugifs has gifs for
+pub const OTAKUGIFS_REACTIONS: &[&str] = &[
+    "airkiss",
+    "angrystare",
+    "bite",
+    "bleh",
+    "blush",
+    "brofist",
+    "celebrate",
+    "cheers",
+    "clap",
+    "confused",
+    "cool",
+    "cry",
+    "cuddle",
+    "dance",
+    "drool",
+    "evillaugh",
+    "facepalm",
+    "handhold",
+    "happy",
+    "headbang",
+    "hug",
+    "huh",
+    "kiss",
+    "laugh",
+    "lick",
+    "love",
+    "mad",
+    "nervous",
+    "no",
+    "nom",
+    "nosebleed",
+    "nuzzle",
+    "nyah",
+    "pat",
+    "peek",
+    "pinch",
+    "poke",
+    "pout",
+    "punch",
+    "roll",
+    "run",
+    "sad",
+    "scared",
+    "shout",
+    "shrug",
+    "shy",
+    "sigh",
+    "sip",
+    "slap",
+    "sleep",
+    "slowclap",
+    "smack",
+    "smile",
+    "smug",
+    "sneeze",
+    "sorry",
+    "stare",
+    "stop",
+    "surprised",
+    "sweat",
+    "thumbsup",
+    "tickle",
+    "tired",
+    "wave",
+    "wink",
+    "woah",
+    "yawn",
+    "yay",
+    "yes",
+];
+
+pub(super) fn default_url() -> String {
+    "https://api.otakugifs.xyz/gif".to_owned()
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct ImageResponse {
+    url: String,
+}
+
+/// Gets gifs from otakugifs.xyz (or another api at `url` that works the same way)
+pub struct OtakugifsProvider {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl OtakugifsProvider {
+    pub fn new(client: reqwest::Client, url: String) -> Self {
+        Self { client, url }
+    }
+}
+
+#[async_trait]
+impl GifProvider for OtakugifsProvider {
+    #[instrument(skip_all, fields(kind = request.kind))]
+    async fn get_gif(&self, request: GifRequest<'_>) -> Result<String, GifError> {
+        info!("sending request to otakugifs");
+        let response = self
+            .client
+            .get(&self.url)
+            .query(&[("reaction", request.kind)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<ImageResponse>()
+            .await?;
+
+        info!("recieved reponse - okay");
+        Ok(response.url)
+    }
+}
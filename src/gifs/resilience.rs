@@ -0,0 +1,337 @@
+//! Timeouts, retries and circuit breaking for gif providers.
+//!
+//! Interactions have to be answered within three seconds, so a slow or broken provider can't be
+//! allowed to hold up a response. Every provider gets wrapped in a [Guarded] provider, which gives
+//! up on requests that take too long, retries failures a bounded number of times and stops
+//! asking a provider at all for a while once it has failed too many times in a row.
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use rand::Rng;
+use serde::Deserialize;
+use serenity::async_trait;
+use tokio::time::Instant;
+use tracing::warn;
+
+use super::{GifError, GifProvider, GifRequest};
+
+/// How requests to gif providers are made
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RequestPolicy {
+    /// How long a single request may take, in milliseconds
+    pub timeout_ms: u64,
+    /// How many times a failed request is retried
+    pub retries: u32,
+    /// The base delay between retries, in milliseconds. This doubles each retry, and is
+    /// randomised a little so that retries don't all line up.
+    pub backoff_ms: u64,
+    /// How many failures in a row it takes for a provider to be skipped
+    pub breaker_threshold: u32,
+    /// How long a provider is skipped for once it has failed too often, in seconds
+    pub breaker_cooldown_secs: u64,
+}
+
+impl Default for RequestPolicy {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 800,
+            retries: 1,
+            backoff_ms: 100,
+            breaker_threshold: 3,
+            breaker_cooldown_secs: 60,
+        }
+    }
+}
+
+impl RequestPolicy {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+
+    fn backoff(&self, retry: u32) -> Duration {
+        let base = self.backoff_ms.saturating_mul(1 << retry.min(16)) as f64;
+        let jitter = rand::thread_rng().gen_range(0.5..1.5);
+        Duration::from_millis((base * jitter) as u64)
+    }
+}
+
+/// What happened when a provider was asked for a gif
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Success,
+    Failure,
+    Timeout,
+    Retry,
+    /// The provider was skipped because its circuit breaker is open
+    ShortCircuit,
+    /// The provider had no gifs for the action
+    Empty,
+}
+
+/// Running totals of each [Outcome] for a provider
+#[derive(Debug, Default)]
+pub struct ProviderMetrics {
+    successes: AtomicU64,
+    failures: AtomicU64,
+    timeouts: AtomicU64,
+    retries: AtomicU64,
+    short_circuits: AtomicU64,
+    empty: AtomicU64,
+}
+
+/// A copy of a provider's metrics at some point in time
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    pub successes: u64,
+    pub failures: u64,
+    pub timeouts: u64,
+    pub retries: u64,
+    pub short_circuits: u64,
+    pub empty: u64,
+}
+
+impl ProviderMetrics {
+    pub fn record(&self, outcome: Outcome) {
+        let counter = match outcome {
+            Outcome::Success => &self.successes,
+            Outcome::Failure => &self.failures,
+            Outcome::Timeout => &self.timeouts,
+            Outcome::Retry => &self.retries,
+            Outcome::ShortCircuit => &self.short_circuits,
+            Outcome::Empty => &self.empty,
+        };
+
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            successes: self.successes.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            short_circuits: self.short_circuits.load(Ordering::Relaxed),
+            empty: self.empty.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl fmt::Display for MetricsSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} succeeded, {} failed, {} timed out, {} retried, {} skipped, {} empty",
+            self.successes,
+            self.failures,
+            self.timeouts,
+            self.retries,
+            self.short_circuits,
+            self.empty
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum BreakerState {
+    /// Requests are allowed through
+    Closed { failures: u32 },
+    /// Requests are skipped until the cooldown is over
+    Open { until: Instant },
+    /// The cooldown is over, and a single request is being let through to see if the provider
+    /// has recovered
+    HalfOpen,
+}
+
+/// Stops requests being made to a provider for a while once it has failed too many times in a row
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    state: Mutex<BreakerState>,
+    threshold: u32,
+    cooldown: Duration,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            state: Mutex::new(BreakerState::Closed { failures: 0 }),
+            threshold: threshold.max(1),
+            cooldown,
+        }
+    }
+
+    /// Lets a request through, if one should be made right now. Its outcome should be recorded
+    /// with the [Permit]
+    pub fn allow(&self) -> Option<Permit<'_>> {
+        let mut state = self.state.lock().unwrap();
+
+        let probe = match *state {
+            BreakerState::Closed { .. } => false,
+            BreakerState::Open { until } if Instant::now() >= until => {
+                *state = BreakerState::HalfOpen;
+                true
+            }
+            BreakerState::Open { .. } | BreakerState::HalfOpen => return None,
+        };
+
+        Some(Permit {
+            breaker: self,
+            probe,
+            finished: false,
+        })
+    }
+
+    fn record_success(&self) {
+        *self.state.lock().unwrap() = BreakerState::Closed { failures: 0 };
+    }
+
+    /// Records a failure, returning true if this opened the breaker
+    fn record_failure(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+
+        let failures = match *state {
+            BreakerState::Closed { failures } => failures + 1,
+            BreakerState::Open { .. } | BreakerState::HalfOpen => self.threshold,
+        };
+
+        if failures >= self.threshold {
+            *state = BreakerState::Open {
+                until: Instant::now() + self.cooldown,
+            };
+            true
+        } else {
+            *state = BreakerState::Closed { failures };
+            false
+        }
+    }
+}
+
+/// A request that a [CircuitBreaker] has let through.
+///
+/// If this is a half open breaker's probe and it's dropped without an outcome (because the request
+/// was cancelled), the breaker goes back to being open with its cooldown already over, so the next
+/// request becomes the probe instead. Otherwise the breaker would wait for an outcome forever.
+pub struct Permit<'a> {
+    breaker: &'a CircuitBreaker,
+    probe: bool,
+    finished: bool,
+}
+
+impl Permit<'_> {
+    pub fn record_success(mut self) {
+        self.finished = true;
+        self.breaker.record_success();
+    }
+
+    /// Records a failure, returning true if this opened the breaker
+    pub fn record_failure(mut self) -> bool {
+        self.finished = true;
+        self.breaker.record_failure()
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if self.probe && !self.finished {
+            let mut state = self.breaker.state.lock().unwrap();
+            if matches!(*state, BreakerState::HalfOpen) {
+                *state = BreakerState::Open {
+                    until: Instant::now(),
+                };
+            }
+        }
+    }
+}
+
+/// A provider wrapped with a timeout, retries and a circuit breaker
+pub struct Guarded {
+    name: String,
+    inner: Box<dyn GifProvider>,
+    policy: RequestPolicy,
+    breaker: CircuitBreaker,
+    metrics: ProviderMetrics,
+}
+
+impl Guarded {
+    pub fn new(name: String, inner: Box<dyn GifProvider>, policy: RequestPolicy) -> Self {
+        let breaker = CircuitBreaker::new(
+            policy.breaker_threshold,
+            Duration::from_secs(policy.breaker_cooldown_secs),
+        );
+
+        Self {
+            name,
+            inner,
+            policy,
+            breaker,
+            metrics: ProviderMetrics::default(),
+        }
+    }
+
+    pub fn metrics(&self) -> &ProviderMetrics {
+        &self.metrics
+    }
+
+    /// Makes one attempt at getting a gif, giving up if it takes too long
+    async fn attempt(&self, request: GifRequest<'_>) -> Result<String, GifError> {
+        match tokio::time::timeout(self.policy.timeout(), self.inner.get_gif(request)).await {
+            Ok(result) => result,
+            Err(_) => Err(GifError::Timeout),
+        }
+    }
+}
+
+#[async_trait]
+impl GifProvider for Guarded {
+    async fn get_gif(&self, request: GifRequest<'_>) -> Result<String, GifError> {
+        let Some(permit) = self.breaker.allow() else {
+            self.metrics.record(Outcome::ShortCircuit);
+            return Err(GifError::CircuitOpen);
+        };
+
+        let mut retry = 0;
+
+        loop {
+            let error = match self.attempt(request).await {
+                Ok(url) => {
+                    self.metrics.record(Outcome::Success);
+                    permit.record_success();
+                    return Ok(url);
+                }
+
+                // The provider answered, it just didn't have anything
+                Err(GifError::NoGifs) => {
+                    self.metrics.record(Outcome::Empty);
+                    permit.record_success();
+                    return Err(GifError::NoGifs);
+                }
+
+                Err(e) => e,
+            };
+
+            if retry < self.policy.retries && error.is_retryable() {
+                self.metrics.record(Outcome::Retry);
+                tokio::time::sleep(self.policy.backoff(retry)).await;
+                retry += 1;
+                continue;
+            }
+
+            self.metrics.record(if matches!(error, GifError::Timeout) {
+                Outcome::Timeout
+            } else {
+                Outcome::Failure
+            });
+
+            if permit.record_failure() {
+                warn!(
+                    "gif provider {} failed too many times, skipping it for {}s",
+                    self.name, self.policy.breaker_cooldown_secs
+                );
+            }
+
+            return Err(error);
+        }
+    }
+}
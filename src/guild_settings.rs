@@ -0,0 +1,109 @@
+//! Settings that each server's admins can change with `/config`.
+//!
+//! Right now that's which commands can be used, and where. Settings are saved in the
+//! [Repository], and kept in memory once they've been read, since they're checked for every
+//! command.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+
+use serenity::all::{ChannelId, GuildId};
+use tokio::sync::{Mutex, RwLock};
+
+use crate::commands::error::CommandError;
+use crate::commands::help::Category;
+use crate::commands::CommandDetails;
+use crate::storage::{Repository, StorageError};
+
+/// How commands can be used in one guild
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GuildSettings {
+    /// Commands that have been turned off
+    pub disabled_commands: BTreeSet<String>,
+    /// Whole categories of commands that have been turned off
+    pub disabled_categories: BTreeSet<Category>,
+    /// Commands that can only be used in certain channels, by command name
+    pub channels: BTreeMap<String, BTreeSet<ChannelId>>,
+}
+
+impl GuildSettings {
+    /// Whether a command can be used in `channel`
+    pub fn check(&self, command: &CommandDetails, channel: ChannelId) -> Result<(), CommandError> {
+        // Otherwise admins could turn off /config, and never be able to turn it back on
+        if !can_be_restricted(command.help.category) {
+            return Ok(());
+        }
+
+        if self.disabled_commands.contains(&command.name)
+            || self.disabled_categories.contains(&command.help.category)
+        {
+            return Err(CommandError::Disabled);
+        }
+
+        match self.channels.get(&command.name) {
+            Some(channels) if !channels.contains(&channel) => Err(CommandError::WrongChannel(
+                channels.iter().copied().collect(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Whether commands in this category can be turned off or restricted to channels
+pub fn can_be_restricted(category: Category) -> bool {
+    category != Category::Admin
+}
+
+/// Every guild's settings
+pub struct GuildSettingsStore {
+    storage: Arc<dyn Repository>,
+    cache: RwLock<HashMap<GuildId, GuildSettings>>,
+    /// Held while settings are being changed, so two changes can't undo each other. This is
+    /// separate from the cache so that commands can still be checked while a change is saved
+    updating: Mutex<()>,
+}
+
+impl GuildSettingsStore {
+    pub fn new(storage: Arc<dyn Repository>) -> Self {
+        Self {
+            storage,
+            cache: RwLock::new(HashMap::new()),
+            updating: Mutex::new(()),
+        }
+    }
+
+    /// The settings for a guild
+    pub async fn get(&self, guild: GuildId) -> Result<GuildSettings, StorageError> {
+        if let Some(settings) = self.cache.read().await.get(&guild) {
+            return Ok(settings.clone());
+        }
+
+        let settings = self.storage.guild_settings(guild).await?;
+
+        // If they were changed while these were being read, the changed ones are newer
+        let mut cache = self.cache.write().await;
+        Ok(cache.entry(guild).or_insert(settings).clone())
+    }
+
+    /// Changes a guild's settings and saves them. If they can't be saved, nothing is changed.
+    pub async fn update<T>(
+        &self,
+        guild: GuildId,
+        change: impl FnOnce(&mut GuildSettings) -> T,
+    ) -> Result<T, StorageError> {
+        let _updating = self.updating.lock().await;
+
+        let mut settings = self.get(guild).await?;
+        let result = change(&mut settings);
+
+        self.storage
+            .set_guild_settings(guild, settings.clone())
+            .await?;
+        self.cache.write().await.insert(guild, settings);
+
+        Ok(result)
+    }
+}
@@ -1,6 +1,10 @@
+use clap::{Parser, Subcommand};
+use config::{Config, ConfigArgs};
 use serenity::prelude::*;
+use std::io;
+use std::path::Path;
+use std::process::ExitCode;
 use std::sync::Arc;
-use std::{fs, io};
 use tokio::signal;
 use tokio::signal::unix::SignalKind;
 use tracing::{error, info};
@@ -10,25 +14,56 @@ use tracing_subscriber::filter::EnvFilter;
 use tracing_subscriber::fmt;
 use tracing_subscriber::layer::SubscriberExt;
 
+mod assets;
 mod commands;
+mod components;
+mod config;
+mod discord;
 mod event_handler;
+mod gifs;
+mod guild_settings;
+mod registration;
+mod storage;
+mod triggers;
 mod utils;
+mod validate;
 
 #[cfg(test)]
 mod test;
 
+/// A fun discord bot for my friends
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Cli {
+    #[command(flatten)]
+    config: ConfigArgs,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check the asset files for mistakes without starting the bot
+    ValidateAssets {
+        /// Fail on warnings as well as errors
+        #[arg(long)]
+        strict: bool,
+    },
+}
+
 /// Set up the logging system
 /// Logs will be sent to stdout for testing purposes, as well as being written to daily log files,
-/// stored in the ./log/ directory (only the last 10 files will be kept).
-fn setup_logging() -> Result<WorkerGuard, Box<dyn std::error::Error>> {
-    std::fs::create_dir_all("./log")?;
+/// stored in the given directory (only the last 10 files will be kept).
+fn setup_logging(log_dir: &Path) -> Result<WorkerGuard, Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(log_dir)?;
 
     let file_appender = tracing_appender::rolling::Builder::new()
         .rotation(Rotation::DAILY)
         .filename_prefix("nano")
         .filename_suffix("log")
         .max_log_files(10)
-        .build("./log")?;
+        .build(log_dir)?;
 
     let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
 
@@ -42,10 +77,38 @@ fn setup_logging() -> Result<WorkerGuard, Box<dyn std::error::Error>> {
 }
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    if let Some(Command::ValidateAssets { strict }) = cli.command {
+        // This doesn't connect to discord, so it doesn't need the whole config to be valid
+        let config = match Config::layered(&cli.config) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("error loading config: {e}");
+                return ExitCode::FAILURE;
+            }
+        };
+
+        return if validate::run(&config, strict) {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        };
+    }
+
+    // Logging isn't set up until we know where to log to, so config errors go straight to stderr
+    let config = match Config::load(&cli.config) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("error loading config: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
     // logging to file only gets done so long as this guard exists
     // so we hold onto it for the duration of the program
-    let _guard = match setup_logging() {
+    let _guard = match setup_logging(&config.log_dir) {
         Ok(guard) => Some(guard),
         Err(e) => {
             fmt::init();
@@ -57,17 +120,48 @@ async fn main() {
     };
 
     // Bot authorisation stuff
-    let token = fs::read_to_string("token.txt").expect("couldnt read token.txt");
-    let intents = GatewayIntents::GUILD_MESSAGES
-        | GatewayIntents::DIRECT_MESSAGES
-        | GatewayIntents::MESSAGE_CONTENT;
+    let token = config.token().to_owned();
+    let intents = config.gateway_intents();
 
     // Set up the framework with our event handler
+    let handler = match event_handler::Handler::new(config) {
+        Ok(handler) => handler,
+        Err(e) => {
+            error!("error setting up: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
     let mut client = Client::builder(&token, intents)
-        .event_handler(event_handler::Handler::new())
+        .event_handler(handler.clone())
         .await
         .expect("Error creating handler");
 
+    // Reload the assets whenever we get a SIGHUP
+    #[cfg(target_family = "unix")]
+    {
+        let handler = handler.clone();
+        let discord =
+            discord::SerenityApi::new(Arc::clone(&client.http), Arc::clone(&client.cache));
+
+        tokio::spawn(async move {
+            let mut sighup = match signal::unix::signal(SignalKind::hangup()) {
+                Ok(sighup) => sighup,
+                Err(e) => {
+                    error!("couldn't register sighup handler: {e}");
+                    return;
+                }
+            };
+
+            while sighup.recv().await.is_some() {
+                info!("sighup recieved, reloading assets");
+                if let Err(e) = handler.reload(&discord).await {
+                    error!("couldn't reload assets, keeping the old ones: {e}");
+                }
+            }
+        });
+    }
+
     // Make sure that Ctrl+C gracefully shuts down the bot
     let shard_manager = Arc::clone(&client.shard_manager);
 
@@ -102,7 +196,16 @@ async fn main() {
     });
 
     // Run the bot
-    if let Err(why) = client.start().await {
+    let result = client.start().await;
+
+    for (name, metrics) in handler.gifs.metrics() {
+        info!("gif provider {name}: {metrics}");
+    }
+
+    if let Err(why) = result {
         error!("Error starting client: {why}");
+        return ExitCode::FAILURE;
     }
+
+    ExitCode::SUCCESS
 }
@@ -0,0 +1,197 @@
+//! Registering slash commands with discord.
+//!
+//! Rather than overwriting every command on each startup, we fetch the commands discord already
+//! has, work out which ones have actually changed and only push those.
+use std::fmt;
+
+use serde_json::Value;
+use serenity::all::{Command, CommandId, CreateCommand, GuildId};
+use tracing::warn;
+
+use crate::discord::DiscordApi;
+
+/// Somewhere commands can be registered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Global,
+    Guild(GuildId),
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Global => write!(f, "global scope"),
+            Target::Guild(id) => write!(f, "guild {id}"),
+        }
+    }
+}
+
+/// The changes needed to turn one set of commands into another
+#[derive(Debug, Default)]
+pub struct CommandDiff<'a> {
+    /// Commands that don't exist yet
+    pub create: Vec<&'a CreateCommand>,
+    /// Commands that exist but are out of date
+    pub update: Vec<(CommandId, &'a CreateCommand)>,
+    /// Commands that exist but shouldn't
+    pub delete: Vec<(CommandId, String)>,
+    /// How many commands are already up to date
+    pub unchanged: usize,
+}
+
+impl CommandDiff<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.create.is_empty() && self.update.is_empty() && self.delete.is_empty()
+    }
+}
+
+impl fmt::Display for CommandDiff<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} created, {} updated, {} deleted, {} unchanged",
+            self.create.len(),
+            self.update.len(),
+            self.delete.len(),
+            self.unchanged
+        )
+    }
+}
+
+/// Works out what needs to change for `existing` to match `desired`.
+pub fn diff<'a>(desired: &'a [CreateCommand], existing: &[Command]) -> CommandDiff<'a> {
+    let mut diff = CommandDiff::default();
+
+    let desired = desired
+        .iter()
+        .map(|cmd| (cmd, serde_json::to_value(cmd).unwrap_or(Value::Null)))
+        .collect::<Vec<_>>();
+
+    let existing = existing
+        .iter()
+        .map(|cmd| (cmd, serde_json::to_value(cmd).unwrap_or(Value::Null)))
+        .collect::<Vec<_>>();
+
+    for (command, value) in &desired {
+        match existing
+            .iter()
+            .find(|(cmd, _)| value["name"] == cmd.name.as_str())
+        {
+            None => diff.create.push(command),
+            Some((_, current)) if is_same(value, current) => diff.unchanged += 1,
+            Some((cmd, _)) => diff.update.push((cmd.id, command)),
+        }
+    }
+
+    for (cmd, _) in &existing {
+        if !desired
+            .iter()
+            .any(|(_, value)| value["name"] == cmd.name.as_str())
+        {
+            diff.delete.push((cmd.id, cmd.name.clone()));
+        }
+    }
+
+    diff
+}
+
+/// Makes the commands registered at `target` match `desired`, only sending requests for the
+/// commands that changed. Returns the changes that were made.
+pub async fn sync<'a>(
+    discord: &dyn DiscordApi,
+    target: Target,
+    desired: &'a [CreateCommand],
+) -> serenity::Result<CommandDiff<'a>> {
+    let existing = discord.get_commands(target).await?;
+    let diff = diff(desired, &existing);
+
+    for command in &diff.create {
+        discord.create_command(target, command).await?;
+    }
+
+    for (id, command) in &diff.update {
+        discord.edit_command(target, *id, command).await?;
+    }
+
+    for (id, name) in &diff.delete {
+        // A stale command hanging around isn't worth failing the whole sync over
+        if let Err(e) = discord.delete_command(target, *id).await {
+            warn!("couldn't delete command \"{name}\" from {target}: {e}");
+        }
+    }
+
+    Ok(diff)
+}
+
+/// Fields of a command that discord fills in itself. They only count if we set them too
+const FILLED_BY_DISCORD: &[&str] = &[
+    "id",
+    "application_id",
+    "guild_id",
+    "version",
+    "type",
+    "handler",
+    "dm_permission",
+    "contexts",
+    "integration_types",
+    "name_localized",
+    "description_localized",
+];
+
+/// Checks whether the command discord has is the one we want, so that fields which were removed
+/// count as changes as well as ones that were added or changed.
+fn is_same(desired: &Value, existing: &Value) -> bool {
+    let desired = normalize(desired);
+    let mut existing = normalize(existing);
+
+    if let Value::Object(existing) = &mut existing {
+        for key in FILLED_BY_DISCORD {
+            if desired.get(key).is_none() {
+                existing.remove(*key);
+            }
+        }
+    }
+
+    equal(&desired, &existing)
+}
+
+/// Discord leaves out fields that are empty, and serenity sends them anyway, so values that are
+/// null, false or empty are removed to make commands comparable.
+fn normalize(value: &Value) -> Value {
+    match value {
+        Value::Object(object) => Value::Object(
+            object
+                .iter()
+                .filter(|(_, value)| !is_empty(value))
+                .map(|(key, value)| (key.clone(), normalize(value)))
+                .collect(),
+        ),
+        Value::Array(array) => Value::Array(array.iter().map(normalize).collect()),
+        value => value.clone(),
+    }
+}
+
+/// Like `==`, but `1` and `1.0` are the same number
+fn equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, value)| b.get(key).is_some_and(|other| equal(value, other)))
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| equal(a, b))
+        }
+        (Value::Number(a), Value::Number(b)) => a.as_f64() == b.as_f64(),
+        (a, b) => a == b,
+    }
+}
+
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null | Value::Bool(false) => true,
+        Value::Array(a) => a.is_empty(),
+        Value::Object(o) => o.is_empty(),
+        _ => false,
+    }
+}
@@ -0,0 +1,122 @@
+//! Everything nano remembers between restarts.
+//!
+//! Commands don't talk to the database directly, they go through a [Repository]. When the bot is
+//! running that's an SQLite file ([SqliteStore]), and tests (or a config without a database path)
+//! get the same thing in memory, so nothing is left behind.
+use std::fmt;
+use std::time::SystemTime;
+
+use serenity::all::{GuildId, UserId};
+use serenity::async_trait;
+
+use crate::guild_settings::GuildSettings;
+
+mod sqlite;
+
+pub use sqlite::SqliteStore;
+
+#[derive(Debug)]
+pub enum StorageError {
+    /// A connection couldn't be taken from the pool
+    Pool(r2d2::Error),
+    /// A query failed
+    Sqlite(rusqlite::Error),
+    /// The database was made by a newer version of nano, which might have changed it in ways
+    /// this one doesn't understand
+    TooNew { version: usize, supported: usize },
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Pool(e) => write!(f, "couldn't connect to the database: {e}"),
+            StorageError::Sqlite(e) => write!(f, "database error: {e}"),
+            StorageError::TooNew { version, supported } => write!(
+                f,
+                "the database is at version {version}, but this version of nano only knows up to {supported}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<r2d2::Error> for StorageError {
+    fn from(e: r2d2::Error) -> Self {
+        StorageError::Pool(e)
+    }
+}
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(e: rusqlite::Error) -> Self {
+        StorageError::Sqlite(e)
+    }
+}
+
+/// One use of an action command, like a hug
+#[derive(Debug, Clone)]
+pub struct ActionUse {
+    pub kind: String,
+    pub user: UserId,
+    /// Who the action was done to, if anyone
+    pub target: Option<UserId>,
+    /// Where it happened, if it wasn't in a DM
+    pub guild: Option<GuildId>,
+    pub at: SystemTime,
+}
+
+/// How many times a user has used an action, everywhere
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActionCounts {
+    /// How many times they've done it to the same target (or to no one)
+    pub count: u64,
+    /// How many times they've done it at all
+    pub total: u64,
+}
+
+/// What nano can save and load
+#[async_trait]
+pub trait Repository: Send + Sync {
+    /// A guild's command settings. Guilds that haven't changed anything get the defaults
+    async fn guild_settings(&self, guild: GuildId) -> Result<GuildSettings, StorageError>;
+
+    /// Replaces all of a guild's command settings
+    async fn set_guild_settings(
+        &self,
+        guild: GuildId,
+        settings: GuildSettings,
+    ) -> Result<(), StorageError>;
+
+    /// Records a use of an action, and returns the user's counts including it
+    async fn record_action(&self, action: ActionUse) -> Result<ActionCounts, StorageError>;
+
+    // The rest count up uses of actions since a time (or ever, if it's None), most first
+
+    /// How many times a user has done each action
+    async fn actions_given(
+        &self,
+        user: UserId,
+        since: Option<SystemTime>,
+    ) -> Result<Vec<(String, u64)>, StorageError>;
+
+    /// How many times each action has been done to a user
+    async fn actions_received(
+        &self,
+        user: UserId,
+        since: Option<SystemTime>,
+    ) -> Result<Vec<(String, u64)>, StorageError>;
+
+    /// Who's done an action the most in a guild
+    async fn top_users(
+        &self,
+        guild: GuildId,
+        kind: String,
+        since: Option<SystemTime>,
+    ) -> Result<Vec<(UserId, u64)>, StorageError>;
+
+    /// How many times each action has been done by anyone, anywhere
+    async fn popular_actions(
+        &self,
+        since: Option<SystemTime>,
+    ) -> Result<Vec<(String, u64)>, StorageError>;
+}
@@ -0,0 +1,387 @@
+//! The SQLite [Repository].
+//!
+//! The schema is built up by [MIGRATIONS], which run when the store is opened. SQLite's
+//! `user_version` pragma records how many have run, so each one only ever runs once.
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::types::FromSql;
+use rusqlite::{Connection, Params};
+use serenity::all::{ChannelId, GuildId, UserId};
+use serenity::async_trait;
+use tracing::info;
+
+use crate::commands::help::Category;
+use crate::guild_settings::GuildSettings;
+
+use super::{ActionCounts, ActionUse, Repository, StorageError};
+
+/// Each migration moves the schema up a version. Once one has been released it mustn't be
+/// changed, so anything else goes in a new one at the end.
+const MIGRATIONS: &[&str] = &[
+    // 1: which commands each guild has turned off or kept to certain channels
+    "CREATE TABLE disabled_commands (
+        guild_id INTEGER NOT NULL,
+        command TEXT NOT NULL,
+        PRIMARY KEY (guild_id, command)
+    );
+    CREATE TABLE disabled_categories (
+        guild_id INTEGER NOT NULL,
+        category TEXT NOT NULL,
+        PRIMARY KEY (guild_id, category)
+    );
+    CREATE TABLE command_channels (
+        guild_id INTEGER NOT NULL,
+        command TEXT NOT NULL,
+        channel_id INTEGER NOT NULL,
+        PRIMARY KEY (guild_id, command, channel_id)
+    );",
+    // 2: every use of an action command, for counting them
+    "CREATE TABLE action_uses (
+        id INTEGER PRIMARY KEY,
+        kind TEXT NOT NULL,
+        user_id INTEGER NOT NULL,
+        target_id INTEGER,
+        guild_id INTEGER,
+        used_at INTEGER NOT NULL
+    );
+    CREATE INDEX action_uses_by_user ON action_uses (user_id, kind, target_id);
+    CREATE INDEX action_uses_by_target ON action_uses (target_id, kind);
+    CREATE INDEX action_uses_by_time ON action_uses (used_at);",
+    // 3: guild leaderboards for /stats
+    "CREATE INDEX action_uses_by_guild ON action_uses (guild_id, kind, used_at);",
+];
+
+/// The most rows a leaderboard query returns
+const LEADERBOARD_LIMIT: i64 = 100;
+
+/// A pool of connections to an SQLite database. Cloning it shares the pool
+#[derive(Clone)]
+pub struct SqliteStore {
+    pool: Pool<SqliteConnectionManager>,
+}
+
+impl SqliteStore {
+    /// Opens (or creates) the database at `path`, and brings its schema up to date
+    pub fn open(path: &Path) -> Result<Self, StorageError> {
+        let manager = SqliteConnectionManager::file(path).with_init(|conn| {
+            // Wait for other connections to finish writing, rather than failing straight away
+            conn.busy_timeout(std::time::Duration::from_secs(5))?;
+            conn.pragma_update(None, "foreign_keys", true)
+        });
+
+        let store = Self::new(Pool::new(manager)?)?;
+
+        // Lets reads happen while something's being written. This is saved in the file, so it
+        // only needs setting once
+        let conn = store.pool.get()?;
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+
+        info!("opened database {}", path.display());
+        Ok(store)
+    }
+
+    /// A database that only lasts as long as the store does
+    pub fn in_memory() -> Result<Self, StorageError> {
+        let manager = SqliteConnectionManager::memory()
+            .with_init(|conn| conn.pragma_update(None, "foreign_keys", true));
+
+        // Connections to a shared in-memory database lock whole tables rather than waiting for
+        // each other like files do, so writes at the same time would fail. With one connection,
+        // they queue for it instead. The database goes when its connection does, so that one is
+        // never closed for being idle or old
+        let pool = Pool::builder()
+            .max_size(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .build(manager)?;
+        Self::new(pool)
+    }
+
+    /// The pool the store's connections come from, for tests to look at
+    #[cfg(test)]
+    pub(crate) fn pool(&self) -> &Pool<SqliteConnectionManager> {
+        &self.pool
+    }
+
+    fn new(pool: Pool<SqliteConnectionManager>) -> Result<Self, StorageError> {
+        migrate(&mut *pool.get()?)?;
+        Ok(Self { pool })
+    }
+
+    /// Runs `f` with a connection from the pool. SQLite blocks, so this happens on a thread
+    /// where that's allowed
+    async fn run<T, F>(&self, f: F) -> Result<T, StorageError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, StorageError> + Send + 'static,
+    {
+        let pool = self.pool.clone();
+        let task = tokio::task::spawn_blocking(move || f(&mut *pool.get()?));
+
+        match task.await {
+            Ok(result) => result,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+}
+
+/// Runs any migrations the database hasn't had yet
+fn migrate(conn: &mut Connection) -> Result<(), StorageError> {
+    let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    let version = version as usize;
+
+    if version > MIGRATIONS.len() {
+        return Err(StorageError::TooNew {
+            version,
+            supported: MIGRATIONS.len(),
+        });
+    }
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        // If a migration fails partway through, none of it happens
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", (i + 1) as i64)?;
+        tx.commit()?;
+
+        info!("migrated database to version {}", i + 1);
+    }
+
+    Ok(())
+}
+
+/// Discord ids fit in an i64 (SQLite's integers) until the year 2084
+fn to_sql(id: u64) -> i64 {
+    id as i64
+}
+
+/// Times are stored as seconds since the unix epoch
+fn timestamp(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+/// The start of a time window, where no start means all time
+fn window_start(since: Option<SystemTime>) -> i64 {
+    since.map_or(0, timestamp)
+}
+
+/// Runs a query that counts something, returning each thing and its count
+fn counts<T: FromSql>(
+    conn: &Connection,
+    sql: &str,
+    params: impl Params,
+) -> Result<Vec<(T, u64)>, StorageError> {
+    let mut query = conn.prepare_cached(sql)?;
+    let rows = query.query_map(params, |row| {
+        Ok((row.get(0)?, row.get::<_, i64>(1)? as u64))
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+#[async_trait]
+impl Repository for SqliteStore {
+    async fn guild_settings(&self, guild: GuildId) -> Result<GuildSettings, StorageError> {
+        self.run(move |conn| {
+            let guild = to_sql(guild.get());
+            let mut settings = GuildSettings::default();
+
+            let mut query =
+                conn.prepare_cached("SELECT command FROM disabled_commands WHERE guild_id = ?1")?;
+            settings.disabled_commands = query
+                .query_map([guild], |row| row.get(0))?
+                .collect::<Result<_, _>>()?;
+
+            let mut query = conn
+                .prepare_cached("SELECT category FROM disabled_categories WHERE guild_id = ?1")?;
+            for category in query.query_map([guild], |row| row.get::<_, String>(0))? {
+                // Categories that have since been removed don't matter any more
+                if let Some(category) = Category::from_name(&category?) {
+                    settings.disabled_categories.insert(category);
+                }
+            }
+
+            let mut query = conn.prepare_cached(
+                "SELECT command, channel_id FROM command_channels WHERE guild_id = ?1",
+            )?;
+            for row in query.query_map([guild], |row| Ok((row.get(0)?, row.get::<_, i64>(1)?)))? {
+                let (command, channel) = row?;
+                settings
+                    .channels
+                    .entry(command)
+                    .or_default()
+                    .insert(ChannelId::new(channel as u64));
+            }
+
+            Ok(settings)
+        })
+        .await
+    }
+
+    async fn set_guild_settings(
+        &self,
+        guild: GuildId,
+        settings: GuildSettings,
+    ) -> Result<(), StorageError> {
+        self.run(move |conn| {
+            let guild = to_sql(guild.get());
+            let tx = conn.transaction()?;
+
+            for table in ["disabled_commands", "disabled_categories", "command_channels"] {
+                tx.execute(&format!("DELETE FROM {table} WHERE guild_id = ?1"), [guild])?;
+            }
+
+            // The statements borrow the transaction, so they're finished with before it's committed
+            {
+                let mut insert =
+                    tx.prepare_cached("INSERT INTO disabled_commands (guild_id, command) VALUES (?1, ?2)")?;
+                for command in &settings.disabled_commands {
+                    insert.execute((guild, command))?;
+                }
+
+                let mut insert = tx.prepare_cached(
+                    "INSERT INTO disabled_categories (guild_id, category) VALUES (?1, ?2)",
+                )?;
+                for category in &settings.disabled_categories {
+                    insert.execute((guild, category.name()))?;
+                }
+
+                let mut insert = tx.prepare_cached(
+                    "INSERT INTO command_channels (guild_id, command, channel_id) VALUES (?1, ?2, ?3)",
+                )?;
+                for (command, channels) in &settings.channels {
+                    for channel in channels {
+                        insert.execute((guild, command, to_sql(channel.get())))?;
+                    }
+                }
+            }
+
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn record_action(&self, action: ActionUse) -> Result<ActionCounts, StorageError> {
+        self.run(move |conn| {
+            let user = to_sql(action.user.get());
+            let target = action.target.map(|target| to_sql(target.get()));
+            let tx = conn.transaction()?;
+
+            tx.execute(
+                "INSERT INTO action_uses (kind, user_id, target_id, guild_id, used_at)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+                (
+                    &action.kind,
+                    user,
+                    target,
+                    action.guild.map(|guild| to_sql(guild.get())),
+                    timestamp(action.at),
+                ),
+            )?;
+
+            // IS rather than =, so that no target matches other uses with no target
+            let count: i64 = tx.query_row(
+                "SELECT COUNT(*) FROM action_uses
+                WHERE kind = ?1 AND user_id = ?2 AND target_id IS ?3",
+                (&action.kind, user, target),
+                |row| row.get(0),
+            )?;
+            let total: i64 = tx.query_row(
+                "SELECT COUNT(*) FROM action_uses WHERE kind = ?1 AND user_id = ?2",
+                (&action.kind, user),
+                |row| row.get(0),
+            )?;
+
+            tx.commit()?;
+            Ok(ActionCounts {
+                count: count as u64,
+                total: total as u64,
+            })
+        })
+        .await
+    }
+
+    async fn actions_given(
+        &self,
+        user: UserId,
+        since: Option<SystemTime>,
+    ) -> Result<Vec<(String, u64)>, StorageError> {
+        self.run(move |conn| {
+            counts(
+                conn,
+                "SELECT kind, COUNT(*) AS uses FROM action_uses
+                WHERE user_id = ?1 AND used_at >= ?2
+                GROUP BY kind ORDER BY uses DESC, kind",
+                (to_sql(user.get()), window_start(since)),
+            )
+        })
+        .await
+    }
+
+    async fn actions_received(
+        &self,
+        user: UserId,
+        since: Option<SystemTime>,
+    ) -> Result<Vec<(String, u64)>, StorageError> {
+        self.run(move |conn| {
+            counts(
+                conn,
+                "SELECT kind, COUNT(*) AS uses FROM action_uses
+                WHERE target_id = ?1 AND used_at >= ?2
+                GROUP BY kind ORDER BY uses DESC, kind",
+                (to_sql(user.get()), window_start(since)),
+            )
+        })
+        .await
+    }
+
+    async fn top_users(
+        &self,
+        guild: GuildId,
+        kind: String,
+        since: Option<SystemTime>,
+    ) -> Result<Vec<(UserId, u64)>, StorageError> {
+        let users = self
+            .run(move |conn| {
+                counts::<i64>(
+                    conn,
+                    "SELECT user_id, COUNT(*) AS uses FROM action_uses
+                    WHERE guild_id = ?1 AND kind = ?2 AND used_at >= ?3
+                    GROUP BY user_id ORDER BY uses DESC, user_id LIMIT ?4",
+                    (
+                        to_sql(guild.get()),
+                        kind,
+                        window_start(since),
+                        LEADERBOARD_LIMIT,
+                    ),
+                )
+            })
+            .await?;
+
+        Ok(users
+            .into_iter()
+            .map(|(user, uses)| (UserId::new(user as u64), uses))
+            .collect())
+    }
+
+    async fn popular_actions(
+        &self,
+        since: Option<SystemTime>,
+    ) -> Result<Vec<(String, u64)>, StorageError> {
+        self.run(move |conn| {
+            counts(
+                conn,
+                "SELECT kind, COUNT(*) AS uses FROM action_uses
+                WHERE used_at >= ?1
+                GROUP BY kind ORDER BY uses DESC, kind",
+                [window_start(since)],
+            )
+        })
+        .await
+    }
+}
@@ -1,4 +1,841 @@
-use crate::commands::{action::ActionCommandData, say_hi::SayHiData};
+//! The tests, and the fakes they share.
+//!
+//! Everything here pretends to come from discord: interactions are all from [USER_ID] in channel
+//! 200 (and guild [GUILD_ID], for guild commands), and [FakeDiscord] records what nano sends back
+//! instead of sending it.
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use serde_json::{json, Value};
+use serenity::all::{
+    ChannelId, Command, CommandId, CommandInteraction, CommandOptionType, CreateCommand,
+    CreateCommandOption, CreateEmbed, CreateInteractionResponse, CreateInteractionResponseFollowup,
+    CreateMessage, EditInteractionResponse, GatewayIntents, GuildId, InteractionId, Message,
+    MessageId, ReactionType, ResolvedValue, User, UserId,
+};
+use serenity::async_trait;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::assets::{AssetError, Assets};
+use crate::commands::action::ActionCommandData;
+use crate::commands::create_command_fn;
+use crate::commands::error::CommandError;
+use crate::commands::help::Category;
+use crate::commands::options::{command_options, CommandOptions};
+use crate::commands::paginator::{Page, Paginator};
+use crate::commands::responder::{Reply, Responder};
+use crate::commands::say_hi::SayHiData;
+use crate::commands::subcommands::{leaf_options, CommandTree, SubCommand, SubCommandGroup};
+use crate::components::{ComponentEvent, CustomId, MAX_CUSTOM_ID_LENGTH};
+use crate::config::{AssetPaths, Config, ConfigArgs};
+use crate::discord::DiscordApi;
+use crate::event_handler::Handler;
+use crate::gifs::{
+    FieldPath, GifError, GifProvider, GifRequest, Guarded, OtakugifsProvider, RequestPolicy,
+};
+use crate::guild_settings::GuildSettings;
+use crate::registration::{diff, Target};
+use crate::storage::{ActionUse, Repository, SqliteStore, StorageError};
+use crate::triggers::{TriggerRule, Triggers};
+use crate::utils::RecentInteractions;
+use crate::validate::{check_actions, check_say_hi, Severity};
+
+const NANO_ID: u64 = 1;
+const USER_ID: u64 = 2;
+const TARGET_ID: u64 = 3;
+/// The guild that [fake_guild_command]s are used in
+const GUILD_ID: u64 = 600;
+
+/// A pretend discord, which records every request made to it as json
+#[derive(Default)]
+struct FakeDiscord {
+    requests: Mutex<Vec<(&'static str, Value)>>,
+}
+
+impl FakeDiscord {
+    fn record(&self, name: &'static str, body: &impl serde::Serialize) {
+        let body = serde_json::to_value(body).unwrap();
+        self.requests.lock().unwrap().push((name, body));
+    }
+
+    /// Takes every request made so far
+    fn take(&self) -> Vec<(&'static str, Value)> {
+        std::mem::take(&mut *self.requests.lock().unwrap())
+    }
+}
+
+#[serenity::async_trait]
+impl DiscordApi for FakeDiscord {
+    async fn create_interaction_response(
+        &self,
+        _: InteractionId,
+        _: &str,
+        response: &CreateInteractionResponse,
+    ) -> serenity::Result<()> {
+        self.record("create_interaction_response", response);
+        Ok(())
+    }
+
+    async fn edit_original_interaction_response(
+        &self,
+        _: &str,
+        edit: &EditInteractionResponse,
+    ) -> serenity::Result<()> {
+        self.record("edit_original_interaction_response", edit);
+        Ok(())
+    }
+
+    async fn delete_original_interaction_response(&self, _: &str) -> serenity::Result<()> {
+        self.record("delete_original_interaction_response", &());
+        Ok(())
+    }
+
+    async fn create_followup_message(
+        &self,
+        _: &str,
+        followup: &CreateInteractionResponseFollowup,
+    ) -> serenity::Result<()> {
+        self.record("create_followup_message", followup);
+        Ok(())
+    }
+
+    async fn send_message(&self, _: ChannelId, message: &CreateMessage) -> serenity::Result<()> {
+        self.record("send_message", message);
+        Ok(())
+    }
+
+    async fn create_reaction(
+        &self,
+        _: ChannelId,
+        _: MessageId,
+        reaction: &ReactionType,
+    ) -> serenity::Result<()> {
+        self.record("create_reaction", reaction);
+        Ok(())
+    }
+
+    async fn current_user(&self) -> serenity::Result<User> {
+        Ok(serde_json::from_value(fake_user(NANO_ID))?)
+    }
+
+    async fn get_user(&self, user: UserId) -> serenity::Result<User> {
+        Ok(serde_json::from_value(fake_user(user.get()))?)
+    }
+
+    async fn nickname(&self, _: GuildId, _: UserId) -> serenity::Result<Option<String>> {
+        Ok(None)
+    }
+
+    async fn get_commands(&self, _: Target) -> serenity::Result<Vec<Command>> {
+        Ok(Vec::new())
+    }
+
+    async fn create_command(&self, _: Target, command: &CreateCommand) -> serenity::Result<()> {
+        self.record("create_command", command);
+        Ok(())
+    }
+
+    async fn edit_command(
+        &self,
+        _: Target,
+        _: CommandId,
+        command: &CreateCommand,
+    ) -> serenity::Result<()> {
+        self.record("edit_command", command);
+        Ok(())
+    }
+
+    async fn delete_command(&self, _: Target, _: CommandId) -> serenity::Result<()> {
+        self.record("delete_command", &());
+        Ok(())
+    }
+}
+
+fn fake_user(id: u64) -> Value {
+    json!({
+        "id": id.to_string(),
+        "username": format!("user{id}"),
+        "discriminator": "0",
+        "global_name": null,
+        "avatar": null,
+    })
+}
+
+fn fake_message(author: u64, content: &str) -> Message {
+    serde_json::from_value(json!({
+        "id": "100",
+        "channel_id": "200",
+        "author": fake_user(author),
+        "content": content,
+        "timestamp": "2024-01-01T00:00:00Z",
+        "edited_timestamp": null,
+        "tts": false,
+        "mention_everyone": false,
+        "mentions": [],
+        "mention_roles": [],
+        "attachments": [],
+        "embeds": [],
+        "pinned": false,
+        "type": 0,
+    }))
+    .unwrap()
+}
+
+/// Every fake interaction is built from this. `kind` is discord's number for the type of
+/// interaction
+fn interaction(kind: u8, data: Value) -> Value {
+    json!({
+        "id": "400",
+        "application_id": "500",
+        "type": kind,
+        "data": data,
+        "channel_id": "200",
+        "user": fake_user(USER_ID),
+        "token": "token",
+        "version": 1,
+        "locale": "en-GB",
+        "entitlements": [],
+        "attachment_size_limit": 8388608,
+    })
+}
+
+/// A slash command from USER_ID, with its options given as json. Any of the fake users can be
+/// used as a user option, and channel 201 as a channel option
+fn fake_command(name: &str, options: Value) -> CommandInteraction {
+    let users = [NANO_ID, USER_ID, TARGET_ID]
+        .into_iter()
+        .map(|id| (id.to_string(), fake_user(id)))
+        .collect::<serde_json::Map<_, _>>();
+
+    let data = json!({
+        "id": "300",
+        "name": name,
+        "type": 1,
+        "options": options,
+        "resolved": {
+            "users": users,
+            "channels": {
+                "201": { "id": "201", "name": "general", "type": 0, "permissions": "0" },
+            },
+        },
+    });
+
+    serde_json::from_value(interaction(2, data)).unwrap()
+}
+
+/// A [fake_command] used in `channel` of GUILD_ID
+fn fake_guild_command(name: &str, channel: u64, options: Value) -> CommandInteraction {
+    let mut cmd = fake_command(name, options);
+    cmd.guild_id = Some(GuildId::new(GUILD_ID));
+    cmd.channel_id = ChannelId::new(channel);
+    cmd
+}
+
+/// An action command, optionally at someone
+fn fake_action(kind: &str, target: Option<u64>) -> CommandInteraction {
+    let options = match target {
+        Some(target) => json!([{ "name": "target", "type": 6, "value": target.to_string() }]),
+        None => json!([]),
+    };
+    fake_command(kind, options)
+}
+
+/// The options for using a subcommand, like `["commands", "disable"]`. Every name but the last is
+/// a group
+fn subcommand_options(path: &[&str], options: Value) -> Value {
+    let (leaf, groups) = path.split_last().unwrap();
+    let leaf = json!([{ "name": leaf, "type": 1, "options": options }]);
+
+    groups.iter().rev().fold(
+        leaf,
+        |options, group| json!([{ "name": group, "type": 2, "options": options }]),
+    )
+}
+
+/// A user typing `typed` into a command's string option
+fn fake_autocomplete(name: &str, option: &str, typed: &str) -> CommandInteraction {
+    let options = json!([{ "name": option, "type": 3, "value": typed, "focused": true }]);
+    let mut json = serde_json::to_value(fake_command(name, options)).unwrap();
+    json["type"] = 4.into();
+    serde_json::from_value(json).unwrap()
+}
+
+/// A button press from USER_ID
+fn fake_button(custom_id: &str) -> ComponentEvent {
+    fake_button_from(USER_ID, custom_id)
+}
+
+fn fake_button_from(user: u64, custom_id: &str) -> ComponentEvent {
+    let mut json = interaction(3, json!({ "custom_id": custom_id, "component_type": 2 }));
+    json["message"] = serde_json::to_value(fake_message(NANO_ID, "")).unwrap();
+    json["user"] = fake_user(user);
+
+    ComponentEvent::Component(Box::new(serde_json::from_value(json).unwrap()))
+}
+
+/// Picking `value` from a select menu
+fn fake_select(custom_id: &str, value: &str) -> ComponentEvent {
+    let ComponentEvent::Component(mut interaction) = fake_button(custom_id) else {
+        unreachable!()
+    };
+
+    interaction.data.kind = serenity::all::ComponentInteractionDataKind::StringSelect {
+        values: vec![value.to_owned()],
+    };
+    ComponentEvent::Component(interaction)
+}
+
+/// Submitting a modal with `value` typed into its `page` text box
+fn fake_modal(custom_id: &str, value: &str) -> ComponentEvent {
+    let data = json!({
+        "custom_id": custom_id,
+        "components": [{
+            "type": 1,
+            "components": [{ "type": 4, "custom_id": "page", "value": value }],
+        }],
+    });
+    let mut json = interaction(5, data);
+    json["message"] = serde_json::to_value(fake_message(NANO_ID, "")).unwrap();
+
+    ComponentEvent::Modal(Box::new(serde_json::from_value(json).unwrap()))
+}
+
+/// The config [fake_handler] uses, with the real assets. It only uses local gifs so nothing goes
+/// over the network, and keeps its database in memory
+fn fake_config() -> Config {
+    let mut config = Config::default();
+    config.gifs.providers = vec![crate::gifs::LOCAL.to_owned()];
+    config.database = None;
+    config
+}
+
+/// A handler which has registered its commands with `discord`
+async fn fake_handler(discord: &FakeDiscord) -> Handler {
+    fake_handler_with(discord, fake_config()).await
+}
+
+async fn fake_handler_with(discord: &FakeDiscord, config: Config) -> Handler {
+    let handler = Handler::new(config).unwrap();
+    handler.register_commands(discord).await;
+    discord.take();
+    handler
+}
+
+/// Pulls the message out of the only request made, which should be an interaction response
+fn only_response(requests: Vec<(&'static str, Value)>) -> Value {
+    match requests.as_slice() {
+        [("create_interaction_response", body)] => body["data"].clone(),
+        other => panic!("expected a single response, got {other:?}"),
+    }
+}
+
+fn content(response: &Value) -> &str {
+    response["content"].as_str().unwrap()
+}
+
+fn footer(response: &Value) -> &str {
+    response["embeds"][0]["footer"]["text"].as_str().unwrap()
+}
+
+/// The custom id of the button in a message which does `action`
+fn button_id(response: &Value, action: &str) -> String {
+    response["components"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .flat_map(|row| row["components"].as_array().unwrap())
+        .map(|button| button["custom_id"].as_str().unwrap())
+        .find(|id| id.split(':').nth(1) == Some(action))
+        .unwrap()
+        .to_owned()
+}
+
+/// An empty directory for a test to put files in. It should be removed at the end of the test
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("nano-test-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn config_files_parse() {
+    let yaml = "token: abc\nregistration:\n  guilds: [1234]\nowners: [\"5678\"]\n";
+    let config = serde_yaml::from_str::<Config>(yaml).unwrap();
+    assert_eq!(config.registration.guilds, vec![GuildId::new(1234)]);
+    assert_eq!(config.owners.len(), 1);
+
+    let toml = "token = \"abc\"\nlog_dir = \"logs\"\n[registration]\nguilds = [1234]\n";
+    let config = toml::from_str::<Config>(toml).unwrap();
+    assert_eq!(config.registration.guilds, vec![GuildId::new(1234)]);
+    assert_eq!(config.log_dir, std::path::PathBuf::from("logs"));
+
+    // Commands left in the place the other mode registers them are removed
+    let guild = Target::Guild(GuildId::new(1234));
+    assert_eq!(config.registration.targets(), [Target::Global]);
+    assert_eq!(config.registration.unused_targets(), [guild]);
+    let guilds = "registration:\n  mode: guilds\n  guilds: [1234]\n";
+    let config = serde_yaml::from_str::<Config>(guilds).unwrap();
+    assert_eq!(config.registration.targets(), [guild]);
+    assert_eq!(config.registration.unused_targets(), [Target::Global]);
+
+    assert!(serde_yaml::from_str::<Config>("tokn: abc").is_err());
+}
+
+#[test]
+fn config_layers_override_in_order() {
+    let mut config = serde_yaml::from_str::<Config>("token: from file\nowners: [1]").unwrap();
+
+    let env = [
+        ("NANO_TOKEN", "from env"),
+        ("NANO_OWNERS", "2, 3"),
+        ("NANO_INTENTS", "GUILD_MESSAGES"),
+        ("UNRELATED", "ignored"),
+    ];
+    config
+        .apply_env(env.iter().map(|(k, v)| (k.to_string(), v.to_string())))
+        .unwrap();
+
+    assert_eq!(config.token(), "from env");
+    assert_eq!(config.owners, vec![UserId::new(2), UserId::new(3)]);
+    assert_eq!(config.gateway_intents(), GatewayIntents::GUILD_MESSAGES);
+
+    config.apply_args(&ConfigArgs {
+        token: Some("from args".to_owned()),
+        ..Default::default()
+    });
+
+    assert_eq!(config.token(), "from args");
+    assert_eq!(config.owners, vec![UserId::new(2), UserId::new(3)]);
+
+    // A token file from a higher layer replaces a token from a lower one
+    let dir = temp_dir("token");
+    let path = dir.join("token");
+    std::fs::write(&path, "from token file\n").unwrap();
+    let env = [("NANO_TOKEN_FILE".to_owned(), path.display().to_string())];
+    let mut layered = serde_yaml::from_str::<Config>("token: from file").unwrap();
+    layered.apply_env(env.into_iter()).unwrap();
+    layered.resolve_token().unwrap();
+    assert_eq!(layered.token(), "from token file");
+
+    // ...and the other way round
+    layered.apply_args(&ConfigArgs {
+        token: Some("from args".to_owned()),
+        ..Default::default()
+    });
+    layered.resolve_token().unwrap();
+    assert_eq!(layered.token(), "from args");
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let bad_env = [("NANO_GUILDS".to_owned(), "not a number".to_owned())];
+    assert!(config.apply_env(bad_env.into_iter()).is_err());
+}
+
+#[test]
+fn command_diff_only_includes_changes() {
+    let existing: Vec<Command> = serde_json::from_value(serde_json::json!([
+        {
+            "id": "1", "application_id": "10", "version": "1", "type": 1,
+            "name": "hug", "description": "Hug someone", "default_member_permissions": null,
+            "options": [{ "type": 6, "name": "target", "description": "the user to target" }],
+        },
+        {
+            "id": "2", "application_id": "10", "version": "1", "type": 1,
+            "name": "sayhi", "description": "Say hi", "default_member_permissions": null,
+        },
+        {
+            "id": "3", "application_id": "10", "version": "1", "type": 1,
+            "name": "removed", "description": "Gone", "default_member_permissions": null,
+        },
+        {
+            "id": "4", "application_id": "10", "version": "1", "type": 1,
+            "name": "pat", "description": "Pat someone", "default_member_permissions": null,
+            "options": [{ "type": 4, "name": "times", "description": "how many", "min_value": 1 }],
+        },
+    ]))
+    .unwrap();
+
+    let desired = vec![
+        CreateCommand::new("hug")
+            .description("Hug someone")
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::User,
+                "target",
+                "the user to target",
+            )),
+        CreateCommand::new("sayhi").description("Say hi to Nano"),
+        CreateCommand::new("help").description("Help"),
+        // Taking something away is a change too
+        CreateCommand::new("pat")
+            .description("Pat someone")
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::Integer,
+                "times",
+                "how many",
+            )),
+    ];
+
+    let diff = diff(&desired, &existing);
+
+    assert_eq!(diff.unchanged, 1);
+    assert_eq!(diff.create.len(), 1);
+    let updated = diff
+        .update
+        .iter()
+        .map(|(id, _)| id.get())
+        .collect::<Vec<_>>();
+    assert_eq!(updated, [2, 4]);
+    assert_eq!(diff.delete.len(), 1);
+    assert_eq!(diff.delete[0].1, "removed");
+}
+
+#[test]
+fn gif_field_paths() {
+    let response = serde_json::json!({
+        "results": [{ "url": "https://example.com/hug.gif", "anime_name": "Nichijou" }],
+        "url": "top level",
+    });
+
+    let path = "results[0].url".parse::<FieldPath>().unwrap();
+    assert_eq!(path.to_string(), "results[0].url");
+    assert_eq!(path.get(&response).unwrap(), "https://example.com/hug.gif");
+
+    let path = "url".parse::<FieldPath>().unwrap();
+    assert_eq!(path.get(&response).unwrap(), "top level");
+
+    let path = "results[1].url".parse::<FieldPath>().unwrap();
+    assert!(path.get(&response).is_none());
+
+    assert!("results[0".parse::<FieldPath>().is_err());
+    assert!("results..url".parse::<FieldPath>().is_err());
+    assert!("results[a]".parse::<FieldPath>().is_err());
+}
+
+/// A canned response for [mock_server] to send
+struct MockResponse {
+    status: u16,
+    body: &'static str,
+    delay: Duration,
+}
+
+impl MockResponse {
+    fn ok(body: &'static str) -> Self {
+        Self {
+            status: 200,
+            body,
+            delay: Duration::ZERO,
+        }
+    }
+
+    fn status(status: u16) -> Self {
+        Self {
+            status,
+            body: "{}",
+            delay: Duration::ZERO,
+        }
+    }
+}
+
+/// Starts a tiny http server that answers each request with the next response in the list
+/// (the last one is repeated forever). Returns the server's url and a count of requests it
+/// has served.
+async fn mock_server(responses: Vec<MockResponse>) -> (String, Arc<AtomicUsize>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/gif", listener.local_addr().unwrap());
+    let hits = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&hits);
+
+    tokio::spawn(async move {
+        loop {
+            let Ok((mut stream, _)) = listener.accept().await else {
+                return;
+            };
+
+            let hit = counter.fetch_add(1, Ordering::SeqCst);
+            let response = &responses[hit.min(responses.len() - 1)];
+
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                match stream.read(&mut buf).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                }
+            }
+
+            tokio::time::sleep(response.delay).await;
+
+            let reply = format!(
+                "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                response.status,
+                response.body.len(),
+                response.body
+            );
+            let _ = stream.write_all(reply.as_bytes()).await;
+        }
+    });
+
+    (url, hits)
+}
+
+fn guarded_otakugifs(url: String, policy: RequestPolicy) -> Guarded {
+    let provider = OtakugifsProvider::new(reqwest::Client::new(), url);
+    Guarded::new("mock".to_owned(), Box::new(provider), policy)
+}
+
+const HUG: GifRequest<'static> = GifRequest {
+    kind: "hug",
+    gifs: &[],
+};
+
+#[tokio::test]
+async fn gif_provider_retries_failures() {
+    let (url, hits) = mock_server(vec![
+        MockResponse::status(503),
+        MockResponse::ok(r#"{"url": "https://example.com/hug.gif"}"#),
+    ])
+    .await;
+
+    let policy = RequestPolicy {
+        retries: 2,
+        backoff_ms: 1,
+        ..Default::default()
+    };
+    let provider = guarded_otakugifs(url, policy);
+
+    assert_eq!(
+        provider.get_gif(HUG).await.unwrap(),
+        "https://example.com/hug.gif"
+    );
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+    let metrics = provider.metrics().snapshot();
+    assert_eq!(metrics.retries, 1);
+    assert_eq!(metrics.successes, 1);
+    assert_eq!(metrics.failures, 0);
+}
+
+#[tokio::test]
+async fn gif_provider_times_out() {
+    let (url, _) = mock_server(vec![MockResponse {
+        delay: Duration::from_secs(5),
+        ..MockResponse::ok(r#"{"url": "too late"}"#)
+    }])
+    .await;
+
+    let policy = RequestPolicy {
+        timeout_ms: 50,
+        retries: 0,
+        ..Default::default()
+    };
+    let provider = guarded_otakugifs(url, policy);
+
+    assert!(matches!(
+        provider.get_gif(HUG).await,
+        Err(GifError::Timeout)
+    ));
+    assert_eq!(provider.metrics().snapshot().timeouts, 1);
+}
+
+#[tokio::test]
+async fn gif_provider_circuit_breaker_skips_failing_provider() {
+    let (url, hits) = mock_server(vec![MockResponse::status(500)]).await;
+
+    let policy = RequestPolicy {
+        retries: 0,
+        breaker_threshold: 2,
+        breaker_cooldown_secs: 60,
+        ..Default::default()
+    };
+    let provider = guarded_otakugifs(url, policy);
+
+    assert!(provider.get_gif(HUG).await.is_err());
+    assert!(provider.get_gif(HUG).await.is_err());
+    assert!(matches!(
+        provider.get_gif(HUG).await,
+        Err(GifError::CircuitOpen)
+    ));
+
+    // The third request should never have reached the server
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+    let metrics = provider.metrics().snapshot();
+    assert_eq!(metrics.failures, 2);
+    assert_eq!(metrics.short_circuits, 1);
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Answer {
+    Gif,
+    Empty,
+    Broken,
+    /// Never answers
+    Hang,
+}
+
+/// A provider which gives each answer in turn, then the last one forever
+struct Scripted {
+    answers: Vec<Answer>,
+    calls: AtomicUsize,
+}
+
+#[async_trait]
+impl GifProvider for Scripted {
+    async fn get_gif(&self, _: GifRequest<'_>) -> Result<String, GifError> {
+        let call = self.calls.fetch_add(1, Ordering::SeqCst);
+        match self.answers[call.min(self.answers.len() - 1)] {
+            Answer::Gif => Ok("https://example.com/hug.gif".to_owned()),
+            Answer::Empty => Err(GifError::NoGifs),
+            Answer::Broken => Err(GifError::MissingField("url".to_owned())),
+            Answer::Hang => std::future::pending().await,
+        }
+    }
+}
+
+#[tokio::test]
+async fn gif_provider_circuit_breaker_probes_finish_however_they_end() {
+    use Answer::*;
+
+    let provider = Scripted {
+        answers: vec![Broken, Empty, Broken, Hang, Gif],
+        calls: AtomicUsize::new(0),
+    };
+    let policy = RequestPolicy {
+        timeout_ms: 60_000,
+        retries: 0,
+        breaker_threshold: 1,
+        breaker_cooldown_secs: 0,
+        ..Default::default()
+    };
+    let provider = Guarded::new("scripted".to_owned(), Box::new(provider), policy);
+
+    assert!(provider.get_gif(HUG).await.is_err());
+
+    // A probe that finds no gifs still shows the provider is working
+    assert!(matches!(provider.get_gif(HUG).await, Err(GifError::NoGifs)));
+    assert!(provider.get_gif(HUG).await.is_err());
+
+    // A probe that's cancelled lets the next request probe instead
+    let cancelled = tokio::time::timeout(Duration::from_millis(50), provider.get_gif(HUG));
+    assert!(cancelled.await.is_err());
+    assert_eq!(
+        provider.get_gif(HUG).await.unwrap(),
+        "https://example.com/hug.gif"
+    );
+
+    assert_eq!(provider.metrics().snapshot().short_circuits, 0);
+}
+
+#[test]
+fn assets_are_checked_when_loaded() {
+    assert!(Assets::load(&Config::default()).is_ok());
+
+    let dir = temp_dir("assets");
+    let actions = dir.join("actions.yaml");
+    std::fs::write(
+        &actions,
+        "- kind: hug\n  description: a hug\n  lonely messages: [hi]\n  targeted messages: [hi]\n\
+         - kind: hug\n  description: another hug\n  lonely messages: []\n",
+    )
+    .unwrap();
+
+    let config = Config {
+        assets: AssetPaths {
+            actions,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let Err(AssetError::Invalid(_, problems)) = Assets::load(&config) else {
+        panic!("invalid actions should not load");
+    };
+
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // half targetable, duplicate kind and no lonely messages
+    assert_eq!(problems.len(), 3);
+}
+
+#[test]
+fn validator_points_at_the_right_lines() {
+    let text =
+        "- kind: Hug\n  description: a hug\n  lonely messages:\n    - <user> hugs <nobody>\n\
+         - kind: zzz\n  description: sleep\n  lonely messages: [<user> sleeps]\n  \
+         providers: [otakugifs, local]\n  gifs: [not a url]\n  reciprocal: nap\n";
+    let actions = serde_yaml::from_str::<Vec<ActionCommandData>>(text).unwrap();
+    let diagnostics = check_actions(
+        Path::new("actions.yaml"),
+        text,
+        &actions,
+        &Config::default(),
+    );
+
+    let found = diagnostics
+        .iter()
+        .map(|d| (d.severity, d.line))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        found,
+        vec![
+            // uppercase command name, unknown placeholder, and otakugifs has no "Hug" with
+            // nothing else to fall back on
+            (Severity::Error, Some(1)),
+            (Severity::Error, Some(4)),
+            (Severity::Error, Some(1)),
+            // zzz can't be done back since it isn't targetable, and there's no nap anyway
+            (Severity::Error, Some(10)),
+            (Severity::Error, Some(10)),
+            // bad gif url, and zzz isn't an otakugifs reaction
+            (Severity::Error, Some(9)),
+            (Severity::Warning, Some(5)),
+        ]
+    );
+
+    let text = "- message: hi <name>!\n  gif: https://example.com/hi.gif\n- message: hi <user>\n  gif: hi.gif\n";
+    let say_hi = serde_yaml::from_str::<Vec<SayHiData>>(text).unwrap();
+    let diagnostics = check_say_hi(Path::new("say_hi.yaml"), text, &say_hi);
+    let lines = diagnostics.iter().map(|d| d.line).collect::<Vec<_>>();
+    assert_eq!(lines, vec![Some(3), Some(4)]);
+
+    // Both buttons doing the same thing would give them the same custom id
+    let text = "- kind: poke
+  description: a poke
+  lonely messages: [<user> pokes]
+  \
+                targeted messages: [<user> pokes <target>]
+  nano messages: [hey]
+  \
+                reciprocal: poke
+  counter: poke
+";
+    let actions = serde_yaml::from_str::<Vec<ActionCommandData>>(text).unwrap();
+    let diagnostics = check_actions(
+        Path::new("actions.yaml"),
+        text,
+        &actions,
+        &Config::default(),
+    );
+    let found = diagnostics
+        .iter()
+        .map(|d| (d.severity, d.line))
+        .collect::<Vec<_>>();
+    assert_eq!(found, vec![(Severity::Error, Some(7))]);
+
+    // Actions can't replace nano's own commands
+    let text = "- kind: stats\n  description: stats\n  lonely messages: [<user> counts]\n";
+    let actions = serde_yaml::from_str::<Vec<ActionCommandData>>(text).unwrap();
+    let mut config = Config::default();
+    config.gifs.providers = vec![crate::gifs::LOCAL.to_owned()];
+    let diagnostics = check_actions(Path::new("actions.yaml"), text, &actions, &config);
+    assert_eq!(diagnostics.len(), 1);
+    assert!(diagnostics[0].message.contains("built in command"));
+}
 
 #[test]
 fn action_commands_parse() {
@@ -10,6 +847,226 @@ fn action_commands_parse() {
     eprintln!("{actions:?}");
 }
 
+#[tokio::test]
+async fn action_commands_respond_offline() {
+    let discord = Arc::new(FakeDiscord::default());
+    let handler = fake_handler(&discord).await;
+
+    handler
+        .dispatch(discord.clone(), fake_action("hug", Some(TARGET_ID)))
+        .await;
+
+    let response = only_response(discord.take());
+    assert!(content(&response).contains(&format!("<@{USER_ID}>")));
+    assert!(content(&response).contains(&format!("<@{TARGET_ID}>")));
+
+    // Doing something to nano gets her own messages
+    handler
+        .dispatch(discord.clone(), fake_action("hug", Some(NANO_ID)))
+        .await;
+
+    let response = only_response(discord.take());
+    let actions = handler.actions.read().await;
+    let hug = actions.iter().find(|action| action.kind == "hug").unwrap();
+    let expected = hug
+        .nano_messages
+        .iter()
+        .flatten()
+        .map(|template| {
+            template
+                .replace("<user>", &format!("<@{USER_ID}>"))
+                .replace("<target>", &format!("<@{NANO_ID}>"))
+        })
+        .collect::<Vec<_>>();
+    assert!(expected.contains(&content(&response).to_owned()));
+
+    // Errors are reported privately
+    handler
+        .dispatch(discord.clone(), fake_command("not-a-command", json!([])))
+        .await;
+    assert_eq!(only_response(discord.take())["flags"], 64);
+}
+
+#[tokio::test]
+async fn action_commands_cool_down() {
+    let discord = Arc::new(FakeDiscord::default());
+    let handler = fake_handler(&discord).await;
+
+    for _ in 0..3 {
+        handler
+            .dispatch(discord.clone(), fake_action("hug", Some(TARGET_ID)))
+            .await;
+        assert!(content(&only_response(discord.take())).contains("<@"));
+    }
+
+    // Each action counts separately
+    handler
+        .dispatch(discord.clone(), fake_action("pat", Some(TARGET_ID)))
+        .await;
+    assert!(!content(&only_response(discord.take())).contains("Slow down"));
+
+    // But a fourth hug is too many, and the user is told privately when they can hug again
+    handler
+        .dispatch(discord.clone(), fake_action("hug", Some(TARGET_ID)))
+        .await;
+    let response = only_response(discord.take());
+    assert_eq!(response["flags"], 64);
+    let content = content(&response);
+    assert!(content.contains("Slow down"));
+    assert!(content.contains("<t:"));
+    assert!(!content.contains("error id"));
+
+    // Other commands aren't limited
+    handler
+        .dispatch(discord.clone(), fake_command("sayhi", json!([])))
+        .await;
+    assert!(only_response(discord.take())["embeds"][0]["image"]["url"].is_string());
+}
+
+#[tokio::test]
+async fn actions_are_counted() {
+    let discord = Arc::new(FakeDiscord::default());
+    let handler = fake_handler(&discord).await;
+
+    let mut footers = Vec::new();
+    for target in [Some(TARGET_ID), Some(TARGET_ID), Some(NANO_ID)] {
+        handler
+            .dispatch(discord.clone(), fake_action("hug", target))
+            .await;
+        footers.push(footer(&only_response(discord.take())).to_owned());
+    }
+
+    assert_eq!(
+        footers,
+        [
+            "Hug #1 (and 1 in total)",
+            "Hug #2 (and 2 in total)",
+            "Hug #1 (and 3 in total)",
+        ]
+    );
+
+    // Each kind of action is counted separately
+    handler
+        .dispatch(discord.clone(), fake_action("pat", Some(TARGET_ID)))
+        .await;
+    let response = only_response(discord.take());
+    assert_eq!(footer(&response), "Pat #1 (and 1 in total)");
+    assert!(!content(&response).contains("<count>") && !content(&response).contains("<total>"));
+
+    // Actions without a tally don't get a footer
+    handler
+        .dispatch(discord.clone(), fake_action("slap", Some(TARGET_ID)))
+        .await;
+    assert!(only_response(discord.take())["embeds"][0]["footer"].is_null());
+}
+
+#[tokio::test]
+async fn targets_can_answer_actions() {
+    let discord = Arc::new(FakeDiscord::default());
+    let handler = fake_handler(&discord).await;
+
+    handler
+        .dispatch(discord.clone(), fake_action("hug", Some(TARGET_ID)))
+        .await;
+    let response = only_response(discord.take());
+    let button = &response["components"][0]["components"][0];
+    assert_eq!(button["label"], "Hug back");
+    let id = button["custom_id"].as_str().unwrap();
+    let rows = response["components"].clone();
+    let press = |user| {
+        let ComponentEvent::Component(mut interaction) = fake_button_from(user, id) else {
+            unreachable!()
+        };
+        interaction.message.components = serde_json::from_value(rows.clone()).unwrap();
+        ComponentEvent::Component(interaction)
+    };
+
+    // Only the target can answer
+    handler
+        .dispatch_component(discord.clone(), press(USER_ID))
+        .await;
+    let response = only_response(discord.take());
+    assert_eq!(response["flags"], 64);
+    assert!(content(&response).contains(&format!("<@{TARGET_ID}>")));
+
+    // If the answer fails, the buttons are put back for another try
+    let untargetable = |targeted: Option<Vec<String>>| {
+        let handler = handler.clone();
+        async move {
+            let mut actions = handler.actions.write().await;
+            let hug = actions.iter_mut().find(|action| action.kind == "hug");
+            std::mem::replace(&mut hug.unwrap().targeted_messages, targeted)
+        }
+    };
+    let targeted = untargetable(None).await;
+    handler
+        .dispatch_component(discord.clone(), press(TARGET_ID))
+        .await;
+    let requests = discord.take();
+    let [("create_interaction_response", update), ("edit_original_interaction_response", restore), ("create_followup_message", error)] =
+        requests.as_slice()
+    else {
+        panic!("expected an update, an edit and a followup, got {requests:?}");
+    };
+    assert_eq!(update["type"], 7);
+    assert_eq!(update["data"]["components"], json!([]));
+    assert_eq!(
+        restore["components"][0]["components"][0]["custom_id"],
+        json!(id)
+    );
+    assert_eq!(error["flags"], 64);
+    untargetable(targeted).await;
+
+    handler
+        .dispatch_component(discord.clone(), press(TARGET_ID))
+        .await;
+
+    // The buttons are taken off the original, and the hug goes the other way
+    let requests = discord.take();
+    let [("create_interaction_response", update), ("create_followup_message", hug)] =
+        requests.as_slice()
+    else {
+        panic!("expected an update and a followup, got {requests:?}");
+    };
+    assert_eq!(update["type"], 7);
+    assert_eq!(update["data"]["components"], json!([]));
+    assert_eq!(footer(hug), "Hug #1 (and 1 in total)");
+    let back = hug["components"][0]["components"][0]["custom_id"]
+        .as_str()
+        .unwrap();
+    assert_eq!(back, format!("action:hug:{USER_ID}:{TARGET_ID}"));
+
+    // Pressing again before the buttons disappear doesn't hug twice
+    handler
+        .dispatch_component(discord.clone(), press(TARGET_ID))
+        .await;
+    let response = only_response(discord.take());
+    assert_eq!(response["flags"], 64);
+    assert_eq!(content(&response), "That's already been answered!");
+
+    // Counters are answered with a different action
+    handler
+        .dispatch(discord.clone(), fake_action("poke", Some(TARGET_ID)))
+        .await;
+    let buttons = only_response(discord.take())["components"][0]["components"].clone();
+    let labels = buttons
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|button| button["label"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(labels, ["Poke back", "Slap"]);
+
+    // Nano doesn't need buttons
+    handler
+        .dispatch(discord.clone(), fake_action("hug", Some(NANO_ID)))
+        .await;
+    assert!(only_response(discord.take())["components"]
+        .as_array()
+        .unwrap()
+        .is_empty());
+}
+
 #[test]
 fn say_hi_details_parse() {
     let file = std::fs::read_to_string("assets/say_hi.yaml").unwrap();
@@ -19,3 +1076,956 @@ fn say_hi_details_parse() {
     let data = parsed.unwrap();
     eprintln!("{data:?}");
 }
+
+#[tokio::test]
+async fn say_hi_responds_offline() {
+    let discord = Arc::new(FakeDiscord::default());
+    let handler = fake_handler(&discord).await;
+
+    handler
+        .dispatch(discord.clone(), fake_command("sayhi", json!([])))
+        .await;
+
+    let response = only_response(discord.take());
+    let title = response["embeds"][0]["title"].as_str().unwrap();
+    assert!(!title.contains("<name>"));
+    assert!(response["embeds"][0]["image"]["url"].is_string());
+}
+
+#[tokio::test]
+async fn deferrals_and_responses_take_turns() {
+    let discord = Arc::new(FakeDiscord::default());
+    let cmd = fake_command("hug", json!([]));
+
+    // A response in time means the deferral never happens
+    let responder = Responder::new(discord.clone(), &cmd);
+    let deferral = responder.defer_after(Duration::from_millis(20));
+    responder.respond(Reply::new().content("hi")).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(40)).await;
+    deferral.finish().await;
+    assert_eq!(content(&only_response(discord.take())), "hi");
+
+    // A late response fills in the deferred one
+    let responder = Responder::new(discord.clone(), &cmd);
+    let deferral = responder.defer_after(Duration::ZERO);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    responder.respond(Reply::new().content("hi")).await.unwrap();
+    deferral.finish().await;
+
+    let requests = discord.take();
+    let names = requests.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        [
+            "create_interaction_response",
+            "edit_original_interaction_response"
+        ]
+    );
+    assert_eq!(requests[0].1["type"], 5);
+    assert_eq!(requests[1].1["content"], "hi");
+
+    // Once the command is done, it's too late to defer
+    let responder = Responder::new(discord.clone(), &cmd);
+    responder
+        .defer_after(Duration::from_millis(20))
+        .finish()
+        .await;
+    tokio::time::sleep(Duration::from_millis(40)).await;
+    assert!(discord.take().is_empty());
+
+    // ...and a deferral that was never filled in is removed
+    let responder = Responder::new(discord.clone(), &cmd);
+    let deferral = responder.defer_after(Duration::ZERO);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    deferral.finish().await;
+
+    let requests = discord.take();
+    let names = requests.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        [
+            "create_interaction_response",
+            "delete_original_interaction_response"
+        ]
+    );
+}
+
+/// The options for searching help for `text`
+fn query(text: &str) -> serde_json::Value {
+    json!([{ "name": "query", "type": 3, "value": text }])
+}
+
+#[tokio::test]
+async fn help_pages_until_accepted() {
+    let discord = Arc::new(FakeDiscord::default());
+    let handler = fake_handler(&discord).await;
+    handler
+        .dispatch(discord.clone(), fake_command("help", json!([])))
+        .await;
+
+    let response = only_response(discord.take());
+    assert!(response["embeds"][0]["fields"].as_array().unwrap().len() > 1);
+    let buttons = response["components"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .flat_map(|row| row["components"].as_array().unwrap())
+        .filter(|component| component["type"] == 2)
+        .count();
+    assert_eq!(buttons, 6);
+
+    let forward = fake_button(&button_id(&response, "forward"));
+    handler.dispatch_component(discord.clone(), forward).await;
+
+    let requests = discord.take();
+    assert_eq!(requests[0].1["type"], 7);
+    let response = only_response(requests);
+    assert!(footer(&response).starts_with("Page 2"));
+
+    // Nothing is kept between presses, so the buttons still work after a restart
+    let restarted = fake_handler(&discord).await;
+    let close = fake_button(&button_id(&response, "close"));
+    restarted.dispatch_component(discord.clone(), close).await;
+
+    let response = only_response(discord.take());
+    assert!(footer(&response).starts_with("Page 2"));
+    assert_eq!(response["components"], json!([]));
+}
+
+#[tokio::test]
+async fn help_has_categories_and_search() {
+    let discord = Arc::new(FakeDiscord::default());
+    let handler = fake_handler(&discord).await;
+    handler
+        .dispatch(discord.clone(), fake_command("help", json!([])))
+        .await;
+    let response = only_response(discord.take());
+
+    // Picking a category from the menu shows just the commands in it
+    let menu = &response["components"][2]["components"][0];
+    assert_eq!(menu["type"], 3);
+    let options = menu["options"].as_array().unwrap();
+    assert!(options.iter().any(|option| option["value"] == "#admin"));
+    assert!(options.iter().all(|option| option["value"] != ""));
+
+    let id = menu["custom_id"].as_str().unwrap();
+    handler
+        .dispatch_component(discord.clone(), fake_select(id, "#admin"))
+        .await;
+    let response = only_response(discord.take());
+    assert_eq!(response["embeds"][0]["title"], "Admin commands:");
+    let fields = response["embeds"][0]["fields"].as_array().unwrap();
+    assert!(fields
+        .iter()
+        .any(|field| field["value"].as_str().unwrap().starts_with("/**reload**")));
+    assert!(!fields
+        .iter()
+        .any(|field| field["value"].as_str().unwrap().starts_with("/**hug**")));
+
+    // ...and going back to all of them shows everything again
+    let all = options[0]["value"].as_str().unwrap();
+    handler
+        .dispatch_component(discord.clone(), fake_select(id, all))
+        .await;
+    let response = only_response(discord.take());
+    assert_eq!(
+        response["embeds"][0]["title"],
+        "Here are all the commands I can perform:"
+    );
+
+    // Searches forgive typos, and look at descriptions too
+    let search = |text: &str| {
+        let (handler, discord) = (handler.clone(), discord.clone());
+        let cmd = fake_command("help", query(text));
+        async move {
+            handler.dispatch(discord.clone(), cmd).await;
+            let response = only_response(discord.take());
+            response["embeds"][0]["fields"][0]["value"]
+                .as_str()
+                .unwrap_or_else(|| panic!("no results: {response}"))
+                .to_owned()
+        }
+    };
+
+    assert!(search("hgu").await.starts_with("/**hug**"));
+    assert!(search("kis").await.starts_with("/**airkiss**"));
+    assert!(search("enemies").await.starts_with("/**angrystare**"));
+
+    // The query is remembered by the buttons
+    handler
+        .dispatch(discord.clone(), fake_command("help", query("hug")))
+        .await;
+    let response = only_response(discord.take());
+    assert!(button_id(&response, "close").ends_with(":?hug"));
+
+    // Long queries are cut down to fit in the custom ids, even when every character takes
+    // more than one byte
+    let long = format!("hug {}", "ハ".repeat(36));
+    handler
+        .dispatch(discord.clone(), fake_command("help", query(&long)))
+        .await;
+    let response = only_response(discord.take());
+    let close = button_id(&response, "close");
+    assert!(close.len() <= MAX_CUSTOM_ID_LENGTH);
+    assert!(close.contains(":?hug ハ"));
+}
+
+#[tokio::test]
+async fn help_suggests_commands() {
+    let discord = Arc::new(FakeDiscord::default());
+    let handler = fake_handler(&discord).await;
+
+    let typing = fake_autocomplete("help", "command", "hgu");
+    handler.dispatch_autocomplete(discord.clone(), typing).await;
+
+    let requests = discord.take();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].1["type"], 8);
+    let choices = requests[0].1["data"]["choices"].as_array().unwrap();
+    assert_eq!(choices[0]["value"], "hug");
+    assert!(choices.len() <= 25);
+
+    // Whatever's suggested has a help page
+    let cmd = fake_command(
+        "help",
+        json!([{ "name": "command", "type": 3, "value": "hug" }]),
+    );
+    handler.dispatch(discord.clone(), cmd).await;
+    let response = only_response(discord.take());
+    assert_eq!(response["embeds"][0]["title"], "Help for /hug:");
+}
+
+#[tokio::test]
+async fn paginator_jumps_and_belongs_to_its_owner() {
+    let discord = Arc::new(FakeDiscord::default());
+    let handler = fake_handler(&discord).await;
+    handler
+        .dispatch(discord.clone(), fake_command("help", json!([])))
+        .await;
+    let first = only_response(discord.take());
+    let total = footer(&first).trim_start_matches("Page 1/").to_owned();
+
+    let press = |event| handler.dispatch_component(discord.clone(), event);
+
+    // Someone else can't turn the pages
+    press(fake_button_from(TARGET_ID, &button_id(&first, "last"))).await;
+    assert_eq!(only_response(discord.take())["flags"], 64);
+
+    press(fake_button(&button_id(&first, "last"))).await;
+    let last = only_response(discord.take());
+    assert_eq!(footer(&last), format!("Page {total}/{total}"));
+
+    press(fake_button(&button_id(&last, "first"))).await;
+    assert!(footer(&only_response(discord.take())).starts_with("Page 1/"));
+
+    // The page number opens a modal, which says where to go
+    press(fake_button(&button_id(&first, "jump"))).await;
+    let requests = discord.take();
+    assert_eq!(requests[0].1["type"], 9);
+    let modal = requests[0].1["data"]["custom_id"]
+        .as_str()
+        .unwrap()
+        .to_owned();
+
+    press(fake_modal(&modal, "2")).await;
+    assert!(footer(&only_response(discord.take())).starts_with("Page 2/"));
+
+    press(fake_modal(&modal, "999")).await;
+    assert_eq!(only_response(discord.take())["flags"], 64);
+
+    // Buttons stop working once their paginator's lifetime is up
+    let page = Page::new(CreateEmbed::new(), 2);
+    let expired = Paginator::new("help", USER_ID.into(), "")
+        .lifetime(Duration::ZERO)
+        .reply(0, page)
+        .into_message();
+    let expired = serde_json::to_value(expired).unwrap();
+    press(fake_button(&button_id(&expired, "forward"))).await;
+    let response = only_response(discord.take());
+    assert_eq!(response["flags"], 64);
+    assert!(content(&response).contains("too old"));
+}
+
+#[tokio::test]
+async fn subcommands_are_built_and_dispatched() {
+    // Each leaf says which one it is, and what it was given
+    let leaf = |name: &'static str| {
+        create_command_fn(move |_discord, _handler, cmd, responder| async move {
+            let given = match leaf_options(&cmd).first().map(|option| &option.value) {
+                Some(ResolvedValue::String(value)) => value.to_string(),
+                _ => "nothing".to_owned(),
+            };
+            let reply = Reply::new().content(format!("{name}: {given}"));
+            responder.respond(reply).await?;
+            Ok(())
+        })
+    };
+
+    let option = CreateCommandOption::new(CommandOptionType::String, "command", "which command");
+    let details = CommandTree::new("config", "Change things")
+        .group(
+            SubCommandGroup::new("commands", "Change commands")
+                .subcommand(
+                    SubCommand::new("enable", "Turn one on", leaf("enable"))
+                        .options([option.clone()]),
+                )
+                .subcommand(
+                    SubCommand::new("disable", "Turn one off", leaf("disable")).options([option]),
+                ),
+        )
+        .subcommand(SubCommand::new("show", "Show everything", leaf("show")))
+        .build();
+
+    let registration = serde_json::to_value(&details.registration).unwrap();
+    assert_eq!(registration["options"][0]["type"], 2);
+    assert_eq!(registration["options"][0]["options"][1]["name"], "disable");
+    assert_eq!(
+        registration["options"][0]["options"][1]["options"][0]["name"],
+        "command"
+    );
+    assert_eq!(registration["options"][1]["type"], 1);
+
+    assert_eq!(details.help.sub_commands.len(), 2);
+    assert_eq!(details.help.sub_commands[0].sub_commands[1].name, "disable");
+
+    let discord = Arc::new(FakeDiscord::default());
+    let handler = fake_handler(&discord).await;
+    let run = |options: Value| {
+        let cmd = fake_command("config", options);
+        let responder = Responder::new(discord.clone(), &cmd);
+        (details.command)(discord.clone(), handler.clone(), cmd, responder)
+    };
+
+    let hug = json!([{ "name": "command", "type": 3, "value": "hug" }]);
+    run(subcommand_options(&["commands", "disable"], hug))
+        .await
+        .unwrap();
+    assert_eq!(content(&only_response(discord.take())), "disable: hug");
+
+    run(subcommand_options(&["show"], json!([]))).await.unwrap();
+    assert_eq!(content(&only_response(discord.take())), "show: nothing");
+
+    let unknown = run(subcommand_options(&["nope"], json!([]))).await;
+    assert!(matches!(unknown, Err(CommandError::UnknownCommand(_))));
+}
+
+command_options! {
+    struct PatOptions {
+        /// who to pat
+        target: User,
+        /// how many times
+        times: Option<i64> => min_int_value(1).max_int_value(10),
+        /// how to pat them
+        style: Option<String> => add_string_choice("Gently", "gently").add_string_choice("Firmly", "firmly"),
+    }
+}
+
+#[test]
+fn typed_options_are_registered_and_parsed() {
+    let options = serde_json::to_value(PatOptions::options()).unwrap();
+    assert_eq!(options[0]["name"], "target");
+    assert_eq!(options[0]["description"], "who to pat");
+    assert_eq!(options[0]["type"], 6);
+    assert_eq!(options[0]["required"], true);
+    assert_eq!(options[1]["required"], false);
+    assert_eq!(options[1]["max_value"], 10);
+    assert_eq!(options[2]["choices"][1]["value"], "firmly");
+
+    let parse = |times: Value| {
+        let target = json!({ "name": "target", "type": 6, "value": TARGET_ID.to_string() });
+        let cmd = fake_command("pat", json!([target, times]));
+        PatOptions::parse(&cmd.data.options())
+    };
+
+    let parsed = parse(json!({ "name": "times", "type": 4, "value": 3 })).unwrap();
+    assert_eq!(parsed.target.id.get(), TARGET_ID);
+    assert_eq!(parsed.times, Some(3));
+    assert_eq!(parsed.style, None);
+
+    // Values of the wrong type are the user's problem, not a panic
+    let wrong = parse(json!({ "name": "times", "type": 3, "value": "lots" }));
+    match wrong {
+        Err(CommandError::BadOption(message)) => assert!(message.contains("a whole number")),
+        _ => panic!("expected a bad option error"),
+    }
+
+    // So are missing required options
+    let cmd = fake_action("pat", None);
+    assert!(matches!(
+        PatOptions::parse(&cmd.data.options()),
+        Err(CommandError::BadOption(_))
+    ));
+}
+
+#[tokio::test]
+async fn messages_get_responses_offline() {
+    let discord = FakeDiscord::default();
+    let handler = fake_handler(&discord).await;
+
+    // Nano only says thank you if she's just done something
+    handler
+        .handle_message(&discord, &fake_message(USER_ID, "good bot"))
+        .await;
+    assert!(discord.take().is_empty());
+
+    handler
+        .handle_message(&discord, &fake_message(USER_ID, "->melo"))
+        .await;
+    let requests = discord.take();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].0, "create_reaction");
+
+    handler
+        .handle_message(&discord, &fake_message(USER_ID, "Good bot!"))
+        .await;
+    let requests = discord.take();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].0, "send_message");
+    assert_eq!(requests[0].1["content"], "I'm not a robot! But thank you.");
+
+    // ...and only once
+    handler
+        .handle_message(&discord, &fake_message(USER_ID, "good bot"))
+        .await;
+    assert!(discord.take().is_empty());
+
+    // Bots are ignored, Nano most of all
+    let mut msg = fake_message(NANO_ID, "->melo");
+    msg.author.bot = true;
+    handler.handle_message(&discord, &msg).await;
+    assert!(discord.take().is_empty());
+}
+
+#[test]
+fn triggers_match_in_one_pass() {
+    let yaml = r#"
+- name: melo
+  match: { prefix: "->melo" }
+  ignore_case: false
+  actions: [{ react: 🍈 }]
+- name: good bot
+  match: { exact: good bot }
+  actions: [{ say: [thanks] }]
+- name: cake
+  match: { word: cake }
+  guilds: [1]
+  cooldown_secs: 60
+  actions: [{ reply: [cake!] }]
+- name: numbers
+  match: { regex: "[0-9]{3}" }
+  actions: [{ gif: ["https://example.com/numbers.gif"] }]
+"#;
+    let rules = serde_yaml::from_str::<Vec<TriggerRule>>(yaml).unwrap();
+    let triggers = Triggers::new(rules).unwrap();
+
+    let matching = |content: &str, guild: Option<u64>| {
+        triggers
+            .matching(content, guild.map(GuildId::new))
+            .map(|(_, rule)| rule.name.as_str())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(matching("->melo", None), ["melo"]);
+    assert_eq!(matching("->MELO", None), Vec::<&str>::new());
+    assert_eq!(matching("Good Bot!!", None), ["good bot"]);
+    assert_eq!(matching("good bot, but", None), Vec::<&str>::new());
+    assert_eq!(matching("i like cake", Some(1)), ["cake"]);
+    assert_eq!(matching("i like cake", Some(2)), Vec::<&str>::new());
+    assert_eq!(matching("i like cakes", Some(1)), Vec::<&str>::new());
+    assert_eq!(matching("->melo 123", None), ["melo", "numbers"]);
+
+    let channel = ChannelId::new(1);
+    assert!(triggers.cool_down(2, channel));
+    assert!(!triggers.cool_down(2, channel));
+    assert!(triggers.cool_down(2, ChannelId::new(2)));
+    // no cooldown
+    assert!(triggers.cool_down(0, channel));
+    assert!(triggers.cool_down(0, channel));
+
+    let bad = "- name: broken\n  match: { regex: \"(\" }\n  actions: [{ react: 🍈 }]\n";
+    let rules = serde_yaml::from_str::<Vec<TriggerRule>>(bad).unwrap();
+    assert!(Triggers::new(rules).is_err());
+}
+
+#[tokio::test]
+async fn recent_interactions_are_per_channel() {
+    let (here, there) = (ChannelId::new(1), ChannelId::new(2));
+    let (me, you) = (UserId::new(1), UserId::new(2));
+
+    let recent = RecentInteractions::new(Duration::from_millis(50), false);
+    recent.record(here, me).await;
+    assert!(recent.is_recent(here, me).await);
+    assert!(recent.is_recent(here, you).await);
+    assert!(!recent.is_recent(there, me).await);
+
+    recent.clear(here, you).await;
+    assert!(!recent.is_recent(here, me).await);
+
+    let recent = RecentInteractions::new(Duration::from_millis(50), true);
+    recent.record(here, me).await;
+    assert!(recent.is_recent(here, me).await);
+    assert!(!recent.is_recent(here, you).await);
+
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    assert!(!recent.is_recent(here, me).await);
+}
+
+#[tokio::test]
+async fn components_are_routed_by_namespace() {
+    let id = CustomId::from_str("help:page:2:some query").unwrap();
+    assert_eq!(id, CustomId::new("help", "page", "2:some query"));
+    assert_eq!(id.to_string(), "help:page:2:some query");
+    assert_eq!(CustomId::from_str("help").ok(), None);
+
+    let discord = Arc::new(FakeDiscord::default());
+    let handler = fake_handler(&discord).await;
+
+    // Unknown namespaces, ids that aren't structured, and help buttons that don't make sense
+    // all get a fallback response
+    for id in ["nothing:here:", "back", "help:forward:nonsense"] {
+        handler
+            .dispatch_component(discord.clone(), fake_button(id))
+            .await;
+
+        let response = only_response(discord.take());
+        assert_eq!(response["flags"], 64);
+        assert!(content(&response).starts_with("Sorry!"));
+    }
+}
+
+/// `/config commands <subcommand>`, used in channel 200
+fn config_command(subcommand: &str, options: Value) -> CommandInteraction {
+    fake_guild_command(
+        "config",
+        200,
+        subcommand_options(&["commands", subcommand], options),
+    )
+}
+
+fn command(name: &str) -> Value {
+    json!([{ "name": "command", "type": 3, "value": name }])
+}
+
+#[tokio::test]
+async fn guilds_can_turn_off_and_restrict_commands() {
+    let dir = temp_dir("guild-settings");
+    let mut config = fake_config();
+    config.database = Some(dir.join("nano.db"));
+
+    let discord = Arc::new(FakeDiscord::default());
+    let handler = fake_handler_with(&discord, config.clone()).await;
+
+    let text = |response: Value| content(&response).to_owned();
+
+    handler
+        .dispatch(discord.clone(), config_command("disable", command("slap")))
+        .await;
+    assert!(text(only_response(discord.take())).starts_with("Done!"));
+
+    // Turned off commands are refused privately, but only in that guild
+    let slap = || fake_guild_command("slap", 200, json!([]));
+    handler.dispatch(discord.clone(), slap()).await;
+    let response = only_response(discord.take());
+    assert_eq!(response["flags"], 64);
+    assert!(text(response).contains("turned off"));
+
+    handler
+        .dispatch(discord.clone(), fake_action("slap", None))
+        .await;
+    assert!(!text(only_response(discord.take())).contains("turned off"));
+
+    // Whole categories can be turned off
+    let fun = json!([{ "name": "category", "type": 3, "value": "fun" }]);
+    handler
+        .dispatch(discord.clone(), config_command("disable", fun))
+        .await;
+    discord.take();
+    handler
+        .dispatch(discord.clone(), fake_guild_command("poke", 200, json!([])))
+        .await;
+    assert!(text(only_response(discord.take())).contains("turned off"));
+
+    // Commands can be kept to certain channels
+    let restrict = json!([
+        { "name": "command", "type": 3, "value": "hug" },
+        { "name": "channel", "type": 7, "value": "201" },
+    ]);
+    handler
+        .dispatch(discord.clone(), config_command("restrict", restrict))
+        .await;
+    assert!(text(only_response(discord.take())).contains("<#201>"));
+
+    handler
+        .dispatch(discord.clone(), fake_guild_command("hug", 200, json!([])))
+        .await;
+    let response = only_response(discord.take());
+    assert_eq!(response["flags"], 64);
+    assert!(text(response).contains("only be used in <#201>"));
+
+    handler
+        .dispatch(discord.clone(), fake_guild_command("hug", 201, json!([])))
+        .await;
+    assert!(text(only_response(discord.take())).contains(&format!("<@{USER_ID}>")));
+
+    // Admin commands can't be turned off, or nobody could turn anything back on
+    handler
+        .dispatch(
+            discord.clone(),
+            config_command("disable", command("config")),
+        )
+        .await;
+    assert!(text(only_response(discord.take())).contains("too important"));
+
+    handler
+        .dispatch(discord.clone(), config_command("list", json!([])))
+        .await;
+    let embed = &only_response(discord.take())["embeds"][0];
+    assert_eq!(embed["fields"][0]["value"], "Fun commands\n/slap");
+    assert_eq!(embed["fields"][1]["value"], "/hug: <#201>");
+
+    // Lists too long for an embed field are cut short
+    let guild = GuildId::new(GUILD_ID);
+    let channels = (0..60).map(|i| ChannelId::new(1_000_000_000_000_000_000 + i));
+    let names = (0..200).map(|i| format!("custom{i}"));
+    handler
+        .guild_settings
+        .update(guild, |settings| {
+            settings
+                .channels
+                .insert("cuddle".to_owned(), channels.collect());
+            settings.disabled_commands.extend(names);
+        })
+        .await
+        .unwrap();
+    handler
+        .dispatch(discord.clone(), config_command("list", json!([])))
+        .await;
+    let embed = &only_response(discord.take())["embeds"][0];
+    for field in embed["fields"].as_array().unwrap() {
+        let value = field["value"].as_str().unwrap();
+        assert!(value.chars().count() <= 1024, "{value}");
+        assert!(value.contains('…'), "{value}");
+    }
+    assert!(embed["fields"][0]["value"]
+        .as_str()
+        .unwrap()
+        .ends_with(" more"));
+    assert!(embed["fields"][1]["value"]
+        .as_str()
+        .unwrap()
+        .ends_with("…\n…and 1 more"));
+
+    // Settings are saved, so a new handler still has them
+    let handler = fake_handler_with(&discord, config).await;
+
+    handler.dispatch(discord.clone(), slap()).await;
+    assert!(text(only_response(discord.take())).contains("turned off"));
+
+    handler
+        .dispatch(discord.clone(), config_command("enable", command("slap")))
+        .await;
+    assert!(text(only_response(discord.take())).contains("Fun commands are still turned off"));
+
+    handler
+        .dispatch(
+            discord.clone(),
+            config_command("unrestrict", command("hug")),
+        )
+        .await;
+    assert!(text(only_response(discord.take())).contains("any channel"));
+
+    handler
+        .dispatch(discord.clone(), fake_guild_command("hug", 200, json!([])))
+        .await;
+    assert!(text(only_response(discord.take())).contains(&format!("<@{USER_ID}>")));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn storage_migrates_and_saves() {
+    let guild = GuildId::new(GUILD_ID);
+    let mut settings = GuildSettings::default();
+    settings.disabled_commands.insert("slap".to_owned());
+    settings.disabled_categories.insert(Category::Fun);
+    settings
+        .channels
+        .entry("hug".to_owned())
+        .or_default()
+        .extend([ChannelId::new(200), ChannelId::new(201)]);
+
+    // Every connection in the pool sees the same in-memory database
+    let store = SqliteStore::in_memory().unwrap();
+    store
+        .set_guild_settings(guild, settings.clone())
+        .await
+        .unwrap();
+    assert_eq!(store.clone().guild_settings(guild).await.unwrap(), settings);
+    assert!(store
+        .guild_settings(GuildId::new(GUILD_ID + 1))
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Saving replaces everything that was there before
+    settings.channels.clear();
+    store
+        .set_guild_settings(guild, settings.clone())
+        .await
+        .unwrap();
+    assert_eq!(store.guild_settings(guild).await.unwrap(), settings);
+
+    // Opening a file again doesn't run the migrations again
+    let dir = temp_dir("storage");
+    let path = dir.join("nano.db");
+    let store = SqliteStore::open(&path).unwrap();
+    store
+        .set_guild_settings(guild, settings.clone())
+        .await
+        .unwrap();
+    drop(store);
+
+    let store = SqliteStore::open(&path).unwrap();
+    assert_eq!(store.guild_settings(guild).await.unwrap(), settings);
+    drop(store);
+
+    // A database from a newer nano isn't touched
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    conn.pragma_update(None, "user_version", 1000).unwrap();
+    drop(conn);
+    assert!(matches!(
+        SqliteStore::open(&path),
+        Err(StorageError::TooNew { version: 1000, .. })
+    ));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn in_memory_storage_outlives_its_connection() {
+    let store = SqliteStore::in_memory().unwrap();
+    let action = ActionUse {
+        kind: "hug".to_owned(),
+        user: UserId::new(USER_ID),
+        target: Some(UserId::new(TARGET_ID)),
+        guild: Some(GuildId::new(GUILD_ID)),
+        at: SystemTime::now(),
+    };
+    store.record_action(action).await.unwrap();
+
+    // The pool never closes its only connection, which would take the database with it
+    let pool = store.pool();
+    assert_eq!(pool.idle_timeout(), None);
+    assert_eq!(pool.max_lifetime(), None);
+
+    drop(pool.get().unwrap());
+    let conn = pool.get().unwrap();
+    let version: u32 = conn
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .unwrap();
+    assert!(version > 0);
+    drop(conn);
+
+    let received = store
+        .actions_received(UserId::new(TARGET_ID), None)
+        .await
+        .unwrap();
+    assert_eq!(received, vec![("hug".to_owned(), 1)]);
+}
+
+#[tokio::test]
+async fn in_memory_storage_handles_concurrent_writes() {
+    let store = SqliteStore::in_memory().unwrap();
+
+    let writes = (0..32).map(|user| {
+        let store = store.clone();
+        tokio::spawn(async move {
+            let action = ActionUse {
+                kind: "hug".to_owned(),
+                user: UserId::new(100 + user),
+                target: Some(UserId::new(TARGET_ID)),
+                guild: Some(GuildId::new(GUILD_ID)),
+                at: SystemTime::now(),
+            };
+            store.record_action(action).await
+        })
+    });
+
+    for write in writes.collect::<Vec<_>>() {
+        write.await.unwrap().unwrap();
+    }
+
+    let received = store
+        .actions_received(UserId::new(TARGET_ID), None)
+        .await
+        .unwrap();
+    assert_eq!(received, vec![("hug".to_owned(), 32)]);
+}
+
+#[tokio::test]
+async fn stats_count_actions_over_time() {
+    let discord = Arc::new(FakeDiscord::default());
+    let handler = fake_handler(&discord).await;
+
+    let now = SystemTime::now();
+    let two_months_ago = now - Duration::from_secs(60 * 24 * 60 * 60);
+    let uses = [
+        ("hug", USER_ID, now),
+        ("hug", USER_ID, now),
+        ("pat", USER_ID, now),
+        ("pat", USER_ID, two_months_ago),
+        ("pat", USER_ID, two_months_ago),
+    ]
+    .into_iter()
+    // Enough other huggers for the leaderboard to need two pages
+    .chain((100..112).map(|user| ("hug", user, now)));
+
+    for (kind, user, at) in uses {
+        let action = ActionUse {
+            kind: kind.to_owned(),
+            user: user.into(),
+            target: Some(TARGET_ID.into()),
+            guild: Some(GUILD_ID.into()),
+            at,
+        };
+        handler.storage.record_action(action).await.unwrap();
+    }
+
+    let stats = |subcommand: &str, options: Value| {
+        let (handler, discord) = (handler.clone(), discord.clone());
+        let cmd = fake_guild_command("stats", 200, subcommand_options(&[subcommand], options));
+        async move {
+            handler.dispatch(discord.clone(), cmd).await;
+            only_response(discord.take())
+        }
+    };
+    let description = |response: &Value| {
+        response["embeds"][0]["description"]
+            .as_str()
+            .unwrap()
+            .to_owned()
+    };
+
+    // Everything is counted by default, and the window narrows it down
+    let response = stats("given", json!([])).await;
+    assert_eq!(
+        description(&response),
+        format!("For <@{USER_ID}>, in every server\n\n**1.** /pat × 3\n**2.** /hug × 2")
+    );
+    assert_eq!(footer(&response), "All time · Page 1/1");
+
+    let week = json!([{ "name": "window", "type": 3, "value": "week" }]);
+    let response = stats("given", week).await;
+    assert!(description(&response).ends_with("**1.** /hug × 2\n**2.** /pat × 1"));
+    assert_eq!(footer(&response), "This week · Page 1/1");
+
+    let target = json!([{ "name": "user", "type": 6, "value": TARGET_ID.to_string() }]);
+    let response = stats("received", target.clone()).await;
+    assert!(description(&response).ends_with("**1.** /hug × 14\n**2.** /pat × 3"));
+    let response = stats("given", target).await;
+    assert!(description(&response).ends_with("Nothing yet!"));
+
+    // Leaderboards are per action, and anyone can look through them
+    let hug = json!([{ "name": "action", "type": 3, "value": "hug" }]);
+    let response = stats("top", hug).await;
+    assert_eq!(response["embeds"][0]["title"], "Top /hug users");
+    assert!(description(&response).starts_with(&format!("**1.** <@{USER_ID}> × 2\n")));
+    assert_eq!(footer(&response), "All time · Page 1/2");
+
+    // ...for a while
+    let forward = button_id(&response, "forward");
+    assert_ne!(forward.split(':').nth(3), Some("0"));
+
+    let forward = fake_button_from(TARGET_ID, &forward);
+    handler.dispatch_component(discord.clone(), forward).await;
+    let response = only_response(discord.take());
+    assert_eq!(
+        description(&response),
+        "**11.** <@109> × 1\n**12.** <@110> × 1\n**13.** <@111> × 1"
+    );
+    assert_eq!(footer(&response), "All time · Page 2/2");
+
+    let unknown = json!([{ "name": "action", "type": 3, "value": "juggle" }]);
+    let response = stats("top", unknown).await;
+    assert!(content(&response).contains("juggle"));
+
+    // The menu switches windows
+    let response = stats("popular", json!([])).await;
+    assert!(description(&response).starts_with("**1.** /hug × 14\n**2.** /pat × 3"));
+
+    let menu = &response["components"][2]["components"][0];
+    let week = menu["options"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|option| option["label"] == "This week")
+        .unwrap()["value"]
+        .as_str()
+        .unwrap();
+    let select = fake_select(menu["custom_id"].as_str().unwrap(), week);
+    handler.dispatch_component(discord.clone(), select).await;
+    let response = only_response(discord.take());
+    assert_eq!(description(&response), "**1.** /hug × 14\n**2.** /pat × 1");
+    assert_eq!(footer(&response), "This week · Page 1/1");
+
+    // That was seven goes, and each server only gets ten a minute
+    for _ in 0..3 {
+        stats("popular", json!([])).await;
+    }
+    let response = stats("popular", json!([])).await;
+    assert_eq!(response["flags"], 64);
+    assert!(content(&response).contains("Slow down"));
+}
+
+#[test]
+fn cooldowns_are_counted_per_bucket() {
+    use crate::commands::cooldown::{Bucket, Cooldown, Cooldowns};
+
+    let here = || fake_guild_command("hug", 200, json!([]));
+    let there = || fake_guild_command("hug", 201, json!([]));
+    let someone_else = || {
+        let mut cmd = here();
+        cmd.user.id = UserId::new(TARGET_ID);
+        cmd
+    };
+    let other_guild = || {
+        let mut cmd = here();
+        cmd.guild_id = Some(GuildId::new(GUILD_ID + 1));
+        cmd
+    };
+    let dm = || {
+        let mut cmd = fake_command("hug", json!([]));
+        cmd.channel_id = ChannelId::new(201);
+        cmd
+    };
+
+    // Each bucket gets one use, then the second command shows whether it shares that bucket
+    let shares = |bucket, second: CommandInteraction| {
+        let cooldowns = Cooldowns::default();
+        let cooldown = [Cooldown::new(bucket, Duration::from_secs(60))];
+        assert!(cooldowns.try_use("hug", &cooldown, &here()).is_ok());
+        cooldowns.try_use("hug", &cooldown, &second).is_err()
+    };
+
+    assert!(shares(Bucket::User, there()));
+    assert!(!shares(Bucket::User, someone_else()));
+
+    assert!(shares(Bucket::Channel, someone_else()));
+    assert!(!shares(Bucket::Channel, there()));
+
+    assert!(shares(Bucket::Guild, there()));
+    assert!(!shares(Bucket::Guild, other_guild()));
+    assert!(!shares(Bucket::Guild, dm()));
+
+    assert!(shares(Bucket::Global, other_guild()));
+    assert!(shares(Bucket::Global, dm()));
+
+    // Cooldowns are per command, and let through as many uses as they're given
+    let cooldowns = Cooldowns::default();
+    let twice = [Cooldown::new(Bucket::Global, Duration::from_secs(60)).uses(2)];
+    assert!(cooldowns.try_use("hug", &twice, &here()).is_ok());
+    assert!(cooldowns.try_use("pat", &twice, &here()).is_ok());
+    assert!(cooldowns.try_use("hug", &twice, &here()).is_ok());
+    let wait = cooldowns.try_use("hug", &twice, &here()).unwrap_err();
+    assert!(wait <= Duration::from_secs(60));
+}
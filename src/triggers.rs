@@ -0,0 +1,185 @@
+//! Responding to plain messages.
+//!
+//! Triggers are rules read from triggers.yaml. Each one has a pattern to look for in messages and
+//! some actions to take when it's found (reacting, or sending a message). Every pattern is
+//! compiled into a single [RegexSet], so a message is checked against all of them in one pass.
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use regex::{Regex, RegexSet};
+use serde::Deserialize;
+use serenity::all::{ChannelId, GuildId};
+use tokio::time::Instant;
+
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct TriggerRule {
+    /// Used for logging
+    pub name: String,
+    #[serde(rename = "match", with = "serde_yaml::with::singleton_map")]
+    pub pattern: Pattern,
+    /// Whether upper and lower case letters count as the same
+    #[serde(default = "default_ignore_case")]
+    pub ignore_case: bool,
+    /// Only fire if nano did something in the channel recently. Firing uses that interaction up.
+    #[serde(default)]
+    pub requires_interaction: bool,
+    /// Firing counts as nano doing something in the channel
+    #[serde(default)]
+    pub counts_as_interaction: bool,
+    /// Only fire in these guilds. If empty, fire everywhere
+    #[serde(default)]
+    pub guilds: Vec<GuildId>,
+    /// How long to wait before firing again in the same channel
+    #[serde(default)]
+    pub cooldown_secs: u64,
+    #[serde(with = "serde_yaml::with::singleton_map_recursive")]
+    pub actions: Vec<TriggerAction>,
+}
+
+fn default_ignore_case() -> bool {
+    true
+}
+
+/// What to look for in a message
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Pattern {
+    /// The message starts with this
+    Prefix(String),
+    /// The message is just this, ignoring any punctuation or spaces around it
+    Exact(String),
+    /// This appears in the message as a whole word (or words)
+    Word(String),
+    /// The message matches this regex anywhere
+    Regex(String),
+}
+
+impl Pattern {
+    /// The regex this pattern compiles to
+    fn to_regex(&self, ignore_case: bool) -> String {
+        let flags = if ignore_case { "(?i)" } else { "" };
+
+        let pattern = match self {
+            Pattern::Prefix(prefix) => format!("^{}", regex::escape(prefix)),
+            Pattern::Exact(text) => format!(r"^\P{{L}}*{}\P{{L}}*$", regex::escape(text)),
+            Pattern::Word(word) => format!(r"\b{}\b", regex::escape(word)),
+            Pattern::Regex(regex) => format!("(?:{regex})"),
+        };
+
+        format!("{flags}{pattern}")
+    }
+}
+
+/// Something to do when a trigger fires
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TriggerAction {
+    /// React to the message with an emoji (either unicode, or a custom one like `<:name:id>`)
+    React(String),
+    /// Send one of these messages in the channel. `<user>` is replaced with the author
+    Say(Vec<String>),
+    /// Reply to the message with one of these messages. `<user>` is replaced with the author
+    Reply(Vec<String>),
+    /// Reply to the message with one of these gifs
+    Gif(Vec<String>),
+}
+
+#[derive(Debug)]
+pub enum TriggerError {
+    /// A rule's pattern isn't a valid regex
+    Regex(String, regex::Error),
+}
+
+impl fmt::Display for TriggerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TriggerError::Regex(name, e) => write!(f, "{name}: invalid pattern: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for TriggerError {}
+
+/// Every trigger rule, compiled so that they can all be checked at once
+#[derive(Debug)]
+pub struct Triggers {
+    rules: Vec<TriggerRule>,
+    set: RegexSet,
+    // When each rule last fired in each channel, for cooldowns
+    fired: Mutex<HashMap<(usize, ChannelId), Instant>>,
+}
+
+impl Triggers {
+    pub fn new(rules: Vec<TriggerRule>) -> Result<Self, TriggerError> {
+        let patterns = rules
+            .iter()
+            .map(|rule| {
+                let pattern = rule.pattern.to_regex(rule.ignore_case);
+                // Compiling each one on its own means errors can say which rule is wrong
+                Regex::new(&pattern).map_err(|e| TriggerError::Regex(rule.name.clone(), e))?;
+                Ok(pattern)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let set = RegexSet::new(patterns).expect("every pattern was already checked");
+
+        Ok(Self {
+            rules,
+            set,
+            fired: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn rules(&self) -> &[TriggerRule] {
+        &self.rules
+    }
+
+    /// The rules whose pattern matches a message, and that apply in `guild`, in the order they
+    /// were written. Cooldowns aren't checked here (see [Triggers::cool_down]).
+    pub fn matching(
+        &self,
+        content: &str,
+        guild: Option<GuildId>,
+    ) -> impl Iterator<Item = (usize, &TriggerRule)> {
+        self.set
+            .matches(content)
+            .into_iter()
+            .map(|i| (i, &self.rules[i]))
+            .filter(move |(_, rule)| {
+                rule.guilds.is_empty() || guild.is_some_and(|guild| rule.guilds.contains(&guild))
+            })
+    }
+
+    /// Starts a rule's cooldown in a channel. Returns false if it's still cooling down from last
+    /// time, in which case it shouldn't fire.
+    pub fn cool_down(&self, rule: usize, channel: ChannelId) -> bool {
+        let cooldown = Duration::from_secs(self.rules[rule].cooldown_secs);
+        if cooldown.is_zero() {
+            return true;
+        }
+
+        let now = Instant::now();
+        let mut fired = self.fired.lock().unwrap();
+        fired.retain(|(i, _), time| {
+            now.duration_since(*time) < Duration::from_secs(self.rules[*i].cooldown_secs)
+        });
+
+        match fired.entry((rule, channel)) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(now);
+                true
+            }
+        }
+    }
+}
+
+impl Default for Triggers {
+    fn default() -> Self {
+        Self::new(Vec::new()).unwrap()
+    }
+}
@@ -1,10 +1,13 @@
-use tokio::sync::RwLock;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio::sync::Mutex;
 use tokio::time::Instant;
 
-use serenity::{
-    all::{GuildId, User, UserId},
-    prelude::Context,
-};
+use serenity::all::{ChannelId, GuildId, User, UserId};
+
+use crate::config::InteractionConfig;
+use crate::discord::DiscordApi;
 
 /// Gets the name of a user.
 ///
@@ -15,9 +18,9 @@ use serenity::{
 /// 3. Username
 ///
 /// In that order.
-pub async fn get_name(ctx: &Context, user: &User, guild: Option<&GuildId>) -> String {
+pub async fn get_name(discord: &dyn DiscordApi, user: &User, guild: Option<&GuildId>) -> String {
     let nickname = match guild {
-        Some(id) => user.nick_in(ctx, id).await,
+        Some(id) => discord.nickname(*id, user.id).await.ok().flatten(),
         None => None,
     };
 
@@ -28,51 +31,73 @@ pub async fn get_name(ctx: &Context, user: &User, guild: Option<&GuildId>) -> St
         .clone()
 }
 
-pub async fn get_nano_icon(ctx: &Context) -> String {
-    let user = ctx.http.get_current_user().await.unwrap();
+pub async fn get_nano_icon(discord: &dyn DiscordApi) -> Option<String> {
+    let user = discord.current_user().await.ok()?;
 
-    user.avatar_url()
-        .unwrap_or_else(|| user.default_avatar_url())
+    Some(user.face())
 }
 
 // meeeeeee :3
-pub async fn get_luna_icon(ctx: &Context) -> Option<String> {
-    UserId::new(253682425165643786)
-        .to_user(ctx)
+pub async fn get_luna_icon(discord: &dyn DiscordApi) -> Option<String> {
+    discord
+        .get_user(UserId::new(253682425165643786))
         .await
         .ok()
         .map(|user| user.face())
 }
 
-/// A thread safe, interior-mutable stopwatch
-/// simply records instants in time and returns them
+/// Keeps track of where nano has recently done something, so she only responds to things like
+/// "good bot" in the places she was actually just used.
+///
+/// Interactions are tracked per channel, and also per user if `per_user` is set. Entries older
+/// than the window are dropped whenever a new one is recorded.
 #[derive(Debug)]
-pub struct SharedStopwatch(RwLock<Option<Instant>>);
+pub struct RecentInteractions {
+    window: Duration,
+    per_user: bool,
+    last: Mutex<HashMap<(ChannelId, Option<UserId>), Instant>>,
+}
 
-impl SharedStopwatch {
-    /// Creates a new, unset stopwatch
-    pub fn new() -> Self {
-        Self(RwLock::new(None))
+impl RecentInteractions {
+    pub fn new(window: Duration, per_user: bool) -> Self {
+        Self {
+            window,
+            per_user,
+            last: Mutex::new(HashMap::new()),
+        }
     }
 
-    /// Sets the stopwatch's start time to now
-    pub async fn set_now(&self) {
-        *self.0.write().await = Some(Instant::now());
+    fn key(&self, channel: ChannelId, user: UserId) -> (ChannelId, Option<UserId>) {
+        (channel, self.per_user.then_some(user))
+    }
+
+    /// Records that nano just did something for this user in this channel
+    pub async fn record(&self, channel: ChannelId, user: UserId) {
+        let now = Instant::now();
+        let mut last = self.last.lock().await;
+
+        last.retain(|_, time| now.duration_since(*time) < self.window);
+        last.insert(self.key(channel, user), now);
     }
 
-    /// Unsets the start time (sets it to None)
-    pub async fn unset(&self) {
-        *self.0.write().await = None;
+    /// Whether nano did something for this user in this channel within the window
+    pub async fn is_recent(&self, channel: ChannelId, user: UserId) -> bool {
+        self.last
+            .lock()
+            .await
+            .get(&self.key(channel, user))
+            .is_some_and(|time| time.elapsed() < self.window)
     }
 
-    /// Get the start time, if any
-    pub async fn get(&self) -> Option<Instant> {
-        *self.0.read().await
+    /// Forgets the last interaction in this channel (for this user)
+    pub async fn clear(&self, channel: ChannelId, user: UserId) {
+        self.last.lock().await.remove(&self.key(channel, user));
     }
 }
 
-impl Default for SharedStopwatch {
+impl Default for RecentInteractions {
     fn default() -> Self {
-        Self::new()
+        let config = InteractionConfig::default();
+        Self::new(config.window(), config.per_user)
     }
 }
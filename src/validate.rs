@@ -0,0 +1,548 @@
+//! Checking asset files for mistakes.
+//!
+//! These checks are run whenever the assets are loaded, and by the `validate-assets` subcommand,
+//! which prints every problem it finds (with file and line numbers) so that broken assets can be
+//! caught before a deploy.
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serenity::all::ReactionType;
+
+use crate::assets::{self, AssetError};
+use crate::commands;
+use crate::commands::action::ActionCommandData;
+use crate::commands::say_hi::SayHiData;
+use crate::config::Config;
+use crate::gifs::{self, OTAKUGIFS_REACTIONS};
+use crate::triggers::{TriggerAction, TriggerError, TriggerRule, Triggers};
+
+/// The placeholders that can be used in each kind of action message
+const LONELY_PLACEHOLDERS: &[&str] = &["user", "count", "total"];
+const TARGETED_PLACEHOLDERS: &[&str] = &["user", "target", "count", "total"];
+/// Footers can't mention anyone, so tallies only get the numbers
+const TALLY_PLACEHOLDERS: &[&str] = &["count", "total"];
+/// The placeholders that can be used in say hi messages
+const SAY_HI_PLACEHOLDERS: &[&str] = &["name"];
+/// The placeholders that can be used in trigger messages
+const TRIGGER_PLACEHOLDERS: &[&str] = &["user"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Something that will break, or be rejected by discord
+    Error,
+    /// Something that will work, but probably isn't what was intended
+    Warning,
+}
+
+/// A problem found in an asset file
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub file: PathBuf,
+    /// The line the problem is on (starting from 1), if we know it
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+
+        match self.line {
+            Some(line) => write!(
+                f,
+                "{}:{line}: {severity}: {}",
+                self.file.display(),
+                self.message
+            ),
+            None => write!(f, "{}: {severity}: {}", self.file.display(), self.message),
+        }
+    }
+}
+
+/// Works out which lines things are on in a yaml file that is a list of maps, so diagnostics can
+/// point at them
+struct SourceMap<'a> {
+    path: &'a Path,
+    lines: Vec<&'a str>,
+    /// The line index where each top level list item starts
+    items: Vec<usize>,
+}
+
+impl<'a> SourceMap<'a> {
+    fn new(path: &'a Path, text: &'a str) -> Self {
+        let lines = text.lines().collect::<Vec<_>>();
+        let items = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| *line == &"-" || line.starts_with("- "))
+            .map(|(i, _)| i)
+            .collect();
+
+        Self { path, lines, items }
+    }
+
+    /// The line that the given text first appears on within the item, or the line the item
+    /// starts on if it can't be found
+    fn line(&self, item: usize, needle: Option<&str>) -> Option<usize> {
+        let start = *self.items.get(item)?;
+        let end = self
+            .items
+            .get(item + 1)
+            .copied()
+            .unwrap_or(self.lines.len());
+
+        let found = needle.and_then(|needle| {
+            // Multi line strings won't appear whole, so just look for their first line
+            let needle = needle.lines().next().unwrap_or(needle).trim();
+            (start..end).find(|&i| self.lines[i].contains(needle))
+        });
+
+        Some(found.unwrap_or(start) + 1)
+    }
+
+    fn diagnostic(
+        &self,
+        severity: Severity,
+        item: usize,
+        needle: Option<&str>,
+        message: String,
+    ) -> Diagnostic {
+        Diagnostic {
+            severity,
+            file: self.path.to_owned(),
+            line: self.line(item, needle),
+            message,
+        }
+    }
+}
+
+/// Finds every `<placeholder>` in a template. Things like mentions and custom emoji, which also
+/// use angle brackets, are skipped.
+fn placeholders(template: &str) -> impl Iterator<Item = &str> {
+    template.split('<').skip(1).filter_map(|part| {
+        let (name, _) = part.split_once('>')?;
+        let is_placeholder =
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_lowercase() || c == '_');
+        is_placeholder.then_some(name)
+    })
+}
+
+/// Whether discord would accept this as the name of a slash command
+fn is_valid_command_name(name: &str) -> bool {
+    let length = name.chars().count();
+
+    (1..=32).contains(&length)
+        && name
+            .chars()
+            .all(|c| (c.is_alphanumeric() || c == '-' || c == '_') && c.to_lowercase().eq(Some(c)))
+}
+
+fn is_valid_gif_url(url: &str) -> bool {
+    reqwest::Url::parse(url).is_ok_and(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+/// Checks the parsed contents of actions.yaml
+pub fn check_actions(
+    path: &Path,
+    text: &str,
+    actions: &[ActionCommandData],
+    config: &Config,
+) -> Vec<Diagnostic> {
+    use Severity::*;
+
+    let source = SourceMap::new(path, text);
+    let mut diagnostics = Vec::new();
+    let mut seen = HashMap::new();
+    let builtin = commands::builtin()
+        .into_iter()
+        .map(|command| command.name)
+        .collect::<Vec<_>>();
+
+    for (i, action) in actions.iter().enumerate() {
+        let kind = &action.kind;
+        let mut report = |severity, needle: Option<&str>, message: String| {
+            diagnostics.push(source.diagnostic(severity, i, needle, format!("{kind}: {message}")));
+        };
+
+        if !is_valid_command_name(kind) {
+            report(
+                Error,
+                None,
+                "not a valid command name (it must be 1-32 lowercase letters, numbers, - or _)"
+                    .to_owned(),
+            );
+        }
+
+        if builtin.contains(kind) {
+            report(
+                Error,
+                None,
+                "there is already a built in command with this name".to_owned(),
+            );
+        }
+
+        if let Some(first) = seen.insert(kind.as_str(), i) {
+            let line = source.line(first, None).unwrap_or_default();
+            report(
+                Error,
+                None,
+                format!("there is already an action with this kind on line {line}"),
+            );
+        }
+
+        let description_length = action.description.chars().count();
+        if !(1..=100).contains(&description_length) {
+            report(
+                Error,
+                Some("description"),
+                format!(
+                    "the description must be 1-100 characters long, but it is {description_length}"
+                ),
+            );
+        }
+
+        if action.lonely_messages.is_empty() {
+            report(
+                Error,
+                Some("lonely messages"),
+                "lonely messages can't be empty".to_owned(),
+            );
+        }
+
+        match (&action.targeted_messages, &action.nano_messages) {
+            (Some(_), None) => report(
+                Error,
+                Some("targeted messages"),
+                "targetable actions need nano messages too".to_owned(),
+            ),
+            (None, Some(_)) => report(
+                Error,
+                Some("nano messages"),
+                "targetable actions need targeted messages too".to_owned(),
+            ),
+            _ => {}
+        }
+
+        let targeted = action.targeted_messages.iter().flatten();
+        let nano = action.nano_messages.iter().flatten();
+
+        for (field, messages) in [
+            ("targeted messages", &action.targeted_messages),
+            ("nano messages", &action.nano_messages),
+        ] {
+            if messages.as_ref().is_some_and(Vec::is_empty) {
+                report(Error, Some(field), format!("{field} can't be empty"));
+            }
+        }
+
+        let templates = action
+            .lonely_messages
+            .iter()
+            .map(|message| (message, LONELY_PLACEHOLDERS))
+            .chain(
+                targeted
+                    .chain(nano)
+                    .map(|message| (message, TARGETED_PLACEHOLDERS)),
+            )
+            .chain(action.tally.iter().map(|tally| (tally, TALLY_PLACEHOLDERS)));
+
+        for (message, allowed) in templates {
+            for placeholder in placeholders(message) {
+                if !allowed.contains(&placeholder) {
+                    report(
+                        Error,
+                        Some(message),
+                        format!("unknown placeholder <{placeholder}>"),
+                    );
+                }
+            }
+        }
+
+        for (field, answer) in [
+            ("reciprocal", &action.reciprocal),
+            ("counter", &action.counter),
+        ] {
+            let Some(answer) = answer else {
+                continue;
+            };
+
+            if !action.targetable() {
+                report(
+                    Error,
+                    Some(field),
+                    format!("only targetable actions can have a {field} button"),
+                );
+            }
+
+            match actions.iter().find(|other| &other.kind == answer) {
+                None => report(
+                    Error,
+                    Some(field),
+                    format!("there is no action called \"{answer}\" for the {field} button"),
+                ),
+                Some(other) if !other.targetable() => report(
+                    Error,
+                    Some(field),
+                    format!("{answer} isn't targetable, so it can't be done back"),
+                ),
+                Some(_) => {}
+            }
+        }
+
+        if action.counter.is_some() && action.counter == action.reciprocal {
+            report(
+                Error,
+                Some("counter"),
+                "the counter button can't do the same action as the reciprocal button".to_owned(),
+            );
+        }
+
+        for gif in &action.gifs {
+            if !is_valid_gif_url(gif) {
+                report(
+                    Error,
+                    Some(gif),
+                    format!("\"{gif}\" is not a valid gif url"),
+                );
+            }
+        }
+
+        let providers = action.providers.as_ref().unwrap_or(&config.gifs.providers);
+
+        for name in providers {
+            let known = config.gifs.custom.contains_key(name)
+                || gifs::builtin_names().contains(&name.as_str());
+
+            if !known {
+                report(
+                    Error,
+                    Some("providers"),
+                    format!("unknown gif provider \"{name}\""),
+                );
+            }
+        }
+
+        // Only the stock otakugifs provider is limited to a known list of reactions
+        let uses_otakugifs = providers.iter().any(|name| name == gifs::OTAKUGIFS)
+            && !config.gifs.custom.contains_key(gifs::OTAKUGIFS);
+
+        if uses_otakugifs && !OTAKUGIFS_REACTIONS.contains(&kind.as_str()) {
+            let has_fallback = providers.iter().any(|name| {
+                (name == gifs::LOCAL && !action.gifs.is_empty())
+                    || (name != gifs::OTAKUGIFS && config.gifs.custom.contains_key(name))
+            });
+
+            let severity = if has_fallback { Warning } else { Error };
+            report(
+                severity,
+                None,
+                "otakugifs doesn't have gifs for this kind of action".to_owned(),
+            );
+        }
+    }
+
+    diagnostics
+}
+
+/// Checks the parsed contents of say_hi.yaml
+pub fn check_say_hi(path: &Path, text: &str, data: &[SayHiData]) -> Vec<Diagnostic> {
+    let source = SourceMap::new(path, text);
+    let mut diagnostics = Vec::new();
+
+    if data.is_empty() {
+        diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            file: path.to_owned(),
+            line: None,
+            message: "there are no messages".to_owned(),
+        });
+    }
+
+    for (i, entry) in data.iter().enumerate() {
+        if entry.message.trim().is_empty() {
+            diagnostics.push(source.diagnostic(
+                Severity::Error,
+                i,
+                None,
+                "the message is empty".to_owned(),
+            ));
+        }
+
+        for placeholder in placeholders(&entry.message) {
+            if !SAY_HI_PLACEHOLDERS.contains(&placeholder) {
+                diagnostics.push(source.diagnostic(
+                    Severity::Error,
+                    i,
+                    Some(&entry.message),
+                    format!("unknown placeholder <{placeholder}>"),
+                ));
+            }
+        }
+
+        if !is_valid_gif_url(entry.gif.trim()) {
+            diagnostics.push(source.diagnostic(
+                Severity::Error,
+                i,
+                Some(entry.gif.trim()),
+                format!("\"{}\" is not a valid gif url", entry.gif.trim()),
+            ));
+        }
+    }
+
+    diagnostics
+}
+
+/// Checks the parsed contents of triggers.yaml
+pub fn check_triggers(path: &Path, text: &str, rules: &[TriggerRule]) -> Vec<Diagnostic> {
+    use Severity::*;
+
+    let source = SourceMap::new(path, text);
+    let mut diagnostics = Vec::new();
+    let mut seen = HashMap::new();
+
+    for (i, rule) in rules.iter().enumerate() {
+        let name = &rule.name;
+        let mut report = |severity, needle: Option<&str>, message: String| {
+            diagnostics.push(source.diagnostic(severity, i, needle, format!("{name}: {message}")));
+        };
+
+        if let Some(first) = seen.insert(name.as_str(), i) {
+            let line = source.line(first, None).unwrap_or_default();
+            report(
+                Warning,
+                None,
+                format!("there is already a trigger with this name on line {line}"),
+            );
+        }
+
+        if let Err(TriggerError::Regex(_, e)) = Triggers::new(vec![rule.clone()]) {
+            report(Error, Some("match"), format!("invalid pattern: {e}"));
+        }
+
+        if rule.actions.is_empty() {
+            report(Error, Some("actions"), "there are no actions".to_owned());
+        }
+
+        for action in &rule.actions {
+            match action {
+                TriggerAction::React(emoji) => {
+                    if ReactionType::try_from(emoji.as_str()).is_err() {
+                        report(Error, Some(emoji), format!("\"{emoji}\" is not an emoji"));
+                    }
+                }
+
+                TriggerAction::Say(messages) | TriggerAction::Reply(messages) => {
+                    if messages.is_empty() {
+                        report(
+                            Error,
+                            Some("actions"),
+                            "message lists can't be empty".to_owned(),
+                        );
+                    }
+
+                    for message in messages {
+                        for placeholder in placeholders(message) {
+                            if !TRIGGER_PLACEHOLDERS.contains(&placeholder) {
+                                report(
+                                    Error,
+                                    Some(message),
+                                    format!("unknown placeholder <{placeholder}>"),
+                                );
+                            }
+                        }
+                    }
+                }
+
+                TriggerAction::Gif(gifs) => {
+                    if gifs.is_empty() {
+                        report(
+                            Error,
+                            Some("actions"),
+                            "gif lists can't be empty".to_owned(),
+                        );
+                    }
+
+                    for gif in gifs {
+                        if !is_valid_gif_url(gif) {
+                            report(
+                                Error,
+                                Some(gif),
+                                format!("\"{gif}\" is not a valid gif url"),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    diagnostics
+}
+
+/// Turns a problem reading an asset file into a diagnostic
+fn read_error(error: AssetError) -> Diagnostic {
+    let (file, line, message) = match error {
+        AssetError::Io(path, e) => (path, None, format!("couldn't read the file: {e}")),
+        AssetError::Parse(path, e) => (path, e.location().map(|l| l.line()), e.to_string()),
+        AssetError::Invalid(path, problems) => (path, None, problems.join(", ")),
+    };
+
+    Diagnostic {
+        severity: Severity::Error,
+        file,
+        line,
+        message,
+    }
+}
+
+/// Checks every asset file, returning all the problems that were found
+pub fn validate_assets(config: &Config) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    let actions_path = &config.assets.actions;
+    match assets::read_yaml::<Vec<ActionCommandData>>(actions_path) {
+        Ok((text, actions)) => {
+            diagnostics.extend(check_actions(actions_path, &text, &actions, config))
+        }
+        Err(e) => diagnostics.push(read_error(e)),
+    }
+
+    let say_hi_path = &config.assets.say_hi;
+    match assets::read_yaml::<Vec<SayHiData>>(say_hi_path) {
+        Ok((text, data)) => diagnostics.extend(check_say_hi(say_hi_path, &text, &data)),
+        Err(e) => diagnostics.push(read_error(e)),
+    }
+
+    let triggers_path = &config.assets.triggers;
+    match assets::read_yaml::<Vec<TriggerRule>>(triggers_path) {
+        Ok((text, rules)) => diagnostics.extend(check_triggers(triggers_path, &text, &rules)),
+        Err(e) => diagnostics.push(read_error(e)),
+    }
+
+    diagnostics
+}
+
+/// Runs the `validate-assets` subcommand, printing every problem found. Returns whether the
+/// assets passed (warnings only fail them if `strict` is set).
+pub fn run(config: &Config, strict: bool) -> bool {
+    let diagnostics = validate_assets(config);
+
+    for diagnostic in &diagnostics {
+        eprintln!("{diagnostic}");
+    }
+
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    let warnings = diagnostics.len() - errors;
+
+    eprintln!("{errors} errors, {warnings} warnings");
+
+    errors == 0 && (!strict || warnings == 0)
+}